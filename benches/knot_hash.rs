@@ -0,0 +1,44 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use advent_of_code_2017::utils::hash::{calculate_knot_hash, calculate_sparse_hash, knot_round};
+
+/// Compares the allocating sparse hash round against the in-place round, running the 64 rounds of a
+/// full knot hash, then times the 128 knot hashes calculated for the AoC 2017 Day 14 grid.
+fn bench_knot_hash(c: &mut Criterion) {
+    let lengths = b"flqrgnkx-127\x11\x1f\x49\x2f\x17";
+    let mut group = c.benchmark_group("knot_hash");
+    group.bench_function("sparse_hash_allocating", |b| {
+        b.iter(|| {
+            let mut list = (0..=255).collect::<Vec<u8>>();
+            let (mut current_pos, mut skip_size) = (0, 0);
+            for _ in 0..64 {
+                list = calculate_sparse_hash(&list, black_box(lengths), &mut current_pos, &mut skip_size);
+            }
+            black_box(list)
+        })
+    });
+    group.bench_function("sparse_hash_in_place", |b| {
+        b.iter(|| {
+            let mut ring = [0u8; 256];
+            for (i, value) in ring.iter_mut().enumerate() {
+                *value = i as u8;
+            }
+            let (mut current_pos, mut skip_size) = (0, 0);
+            for _ in 0..64 {
+                knot_round(&mut ring, black_box(lengths), &mut current_pos, &mut skip_size);
+            }
+            black_box(ring)
+        })
+    });
+    group.bench_function("day14_grid", |b| {
+        b.iter(|| {
+            for row in 0..128 {
+                black_box(calculate_knot_hash(&format!("flqrgnkx-{}", row)));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_knot_hash);
+criterion_main!(benches);
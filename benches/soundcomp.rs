@@ -0,0 +1,35 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use advent_of_code_2017::utils::machines::{parse_program, Backend, Network, SoundComputer, SoundIo, Topology};
+
+/// Compares the reference interpreter against the compiled backend on the AoC 2017 Day 18 program,
+/// running both the single-mode frequency recovery and the double-mode duet.
+fn bench_day_18(c: &mut Criterion) {
+    let input = std::fs::read_to_string("./input/2017/day18.txt").unwrap();
+    let program = parse_program(input.trim()).unwrap();
+    let mut group = c.benchmark_group("day18");
+    for backend in [Backend::Interpreter, Backend::Compiled] {
+        group.bench_with_input(BenchmarkId::new("single_mode", format!("{:?}", backend)), &backend, |b, backend| {
+            b.iter(|| {
+                let mut sound_comp = SoundComputer::with_io(&program, SoundIo::new());
+                sound_comp.set_backend(*backend);
+                sound_comp.execute_single_mode().unwrap();
+                black_box(sound_comp.last_recovered_freq())
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("duet", format!("{:?}", backend)), &backend, |b, backend| {
+            b.iter(|| {
+                let mut machines = vec![SoundComputer::new(&program), SoundComputer::new(&program)];
+                machines[1].update_register('p', 1);
+                for machine in machines.iter_mut() {
+                    machine.set_backend(*backend);
+                }
+                black_box(Network::new(machines, Topology::Pairwise).run().unwrap().sends[1])
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_day_18);
+criterion_main!(benches);
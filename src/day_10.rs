@@ -9,22 +9,17 @@ fn generate_input(input: &str) -> String {
 fn solve_part_1(input: &String) -> u64 {
     // Convert the input into list of lengths
     let lengths = input.split(",").map(|x| x.parse::<u8>().unwrap()).collect::<Vec<u8>>();
-    // Initialise the list, current position and skip size
-    let mut list = (0..=255).collect::<Vec<u8>>();
-    let mut current_pos: usize = 0;
-    let mut skip_size: usize = 0;
-    // Process each length
-    list = calculate_sparse_hash(&mut list, &lengths, &mut current_pos, &mut skip_size);
+    // Process each length in a single round over a 256 element ring, without the length suffix
+    let params = KnotHashParams::new(256, 1, &[], 16).unwrap();
+    let list = params.sparse_hash(&lengths);
     // Return the product of the first two values in the list after processing
     return list[0] as u64 * list[1] as u64;
 }
 
 #[aoc(day10, part2)]
 fn solve_part_2(input: &String) -> String {
-    let knot_hash = calculate_knot_hash(input);
     // Convert dense hash to hexadecimal representation
-    let hex_output = hex::encode(knot_hash);
-    return hex_output;
+    return calculate_knot_hash(input).to_hex();
 }
 
 #[cfg(test)]
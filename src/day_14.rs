@@ -16,12 +16,8 @@ fn solve_part_1(input: &String) -> u64 {
     for i in 0..=127 {
         // Generate hash input for current row
         let hash_input = format!("{}-{}", input, i.to_string());
-        // Calculate the knot hash, output being 16 u8 values
-        let knot_hash = calculate_knot_hash(&hash_input);
-        // Count how many squares are used in the current row
-        for value in knot_hash {
-            squares_used += value.count_ones();
-        }
+        // Count how many squares are used in the current row, given by the bits set in the knot hash
+        squares_used += calculate_knot_hash(&hash_input).count_ones();
     }
     return squares_used as u64;
 }
@@ -31,23 +27,10 @@ fn solve_part_2(input: &String) -> u64 {
     let mut grid_state = HashMap::<Point2D, bool>::new();
     for row in 0..=127 {
         let hash_input = format!("{}-{}", input, row.to_string());
-        let knot_hash = calculate_knot_hash(&hash_input);
-        for i in 0..16 {
-            let value = knot_hash[i];
-            for bit in 0..8 {
-                let bit_value = (value >> (7 - bit)) & 0x01;
-                // Calculate grid co-ordinates
-                let x = (i * 8 + bit as usize) as i64;
-                let y = row;
-                let position = Point2D::new(x, y);
-                // println!("x: {} --- y: {} --- bit_value: {} --- value: {}", x, y, bit_value, value);
-                // Insert bit state into grid
-                if bit_value == 1 {
-                    grid_state.insert(position, true);
-                } else {
-                    grid_state.insert(position, false);
-                }
-            }
+        let bit_row = calculate_knot_hash(&hash_input).to_bit_row();
+        // Insert the state of each square in the row into the grid
+        for (x, used) in bit_row.iter().enumerate() {
+            grid_state.insert(Point2D::new(x as i64, row), *used);
         }
     }
     // Determine regions present by finding what
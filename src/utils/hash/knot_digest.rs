@@ -0,0 +1,99 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// 16-byte dense hash produced by the knot hash algorithm from AoC 2017 Day 10, Part 2.
+///
+/// Bits are ordered from the most significant bit of the first byte to the least significant bit of
+/// the last byte, matching the order of the squares in a row of the AoC 2017 Day 14 grid.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct KnotDigest([u8; 16]);
+
+impl KnotDigest {
+    pub fn new(bytes: [u8; 16]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        return &self.0;
+    }
+
+    /// Returns the lowercase hexadecimal representation of the digest.
+    pub fn to_hex(&self) -> String {
+        return hex::encode(self.0);
+    }
+
+    /// Returns an iterator over the 128 bits of the digest, with a set bit given as true.
+    pub fn bits(&self) -> impl Iterator<Item = bool> + '_ {
+        return self.0.iter().flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1 == 1));
+    }
+
+    /// Returns the number of set bits in the digest.
+    pub fn count_ones(&self) -> u32 {
+        return self.0.iter().map(|x| x.count_ones()).sum();
+    }
+
+    /// Returns the bits of the digest as a row of 128 flags, with a set bit given as true.
+    pub fn to_bit_row(&self) -> [bool; 128] {
+        let mut row = [false; 128];
+        for (square, bit) in row.iter_mut().zip(self.bits()) {
+            *square = bit;
+        }
+        return row;
+    }
+}
+
+impl From<[u8; 16]> for KnotDigest {
+    fn from(bytes: [u8; 16]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<KnotDigest> for [u8; 16] {
+    fn from(digest: KnotDigest) -> Self {
+        digest.0
+    }
+}
+
+impl AsRef<[u8]> for KnotDigest {
+    fn as_ref(&self) -> &[u8] {
+        return &self.0;
+    }
+}
+
+impl fmt::Display for KnotDigest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl FromStr for KnotDigest {
+    type Err = hex::FromHexError;
+
+    /// Parses a digest from its hexadecimal representation, which must be exactly 32 characters.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0u8; 16];
+        hex::decode_to_slice(s, &mut bytes)?;
+        return Ok(Self(bytes));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_knot_digest_conversions() {
+        let digest = "a0c20170000000000000000000000001".parse::<KnotDigest>().unwrap();
+        assert_eq!("a0c20170000000000000000000000001", digest.to_hex());
+        assert_eq!(digest.to_hex(), digest.to_string());
+        assert_eq!(0xa0, digest.as_bytes()[0]);
+        assert_eq!(10, digest.count_ones());
+        assert_eq!(digest.count_ones() as usize, digest.bits().filter(|x| *x).count());
+        let row = digest.to_bit_row();
+        assert_eq!([true, false, true, false, false, false, false, false], row[..8]);
+        assert!(row[127]);
+        assert_eq!(digest.bits().collect::<Vec<bool>>(), row.to_vec());
+        assert!("a0c2".parse::<KnotDigest>().is_err());
+        assert!("z0c20170000000000000000000000001".parse::<KnotDigest>().is_err());
+    }
+}
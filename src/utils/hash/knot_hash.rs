@@ -1,38 +1,285 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hasher};
+
+use digest::consts::U16;
+use digest::{FixedOutput, FixedOutputReset, HashMarker, Output, OutputSizeUser, Reset, Update};
+
+use super::knot_digest::KnotDigest;
+
+/// Lengths appended to the input of the knot hash before the sparse hash rounds are performed.
+const LENGTH_SUFFIX: [u8; 5] = [17, 31, 73, 47, 23];
+
+/// Reasons that a set of knot hash parameters can be rejected.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum KnotHashParamsError {
+    /// Ring must contain at least one element.
+    EmptyRing,
+    /// Ring elements are bytes, so the ring cannot hold more than 256 elements.
+    RingTooLarge(usize),
+    /// Fold width must be at least one.
+    ZeroFoldWidth,
+    /// Ring size must be a multiple of the fold width, so the ring folds into whole blocks.
+    FoldWidthMismatch { ring_size: usize, fold_width: usize },
+}
+
+impl fmt::Display for KnotHashParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KnotHashParamsError::EmptyRing => write!(f, "ring size must be at least 1"),
+            KnotHashParamsError::RingTooLarge(size) => write!(f, "ring size {} is larger than 256", size),
+            KnotHashParamsError::ZeroFoldWidth => write!(f, "fold width must be at least 1"),
+            KnotHashParamsError::FoldWidthMismatch { ring_size, fold_width } => {
+                write!(f, "ring size {} is not a multiple of fold width {}", ring_size, fold_width)
+            }
+        }
+    }
+}
+
+impl Error for KnotHashParamsError {}
+
+/// Parameters of the knot hash algorithm. The default parameters are those of the full knot hash
+/// from AoC 2017 Day 10, Part 2: a ring of 256 elements, 64 rounds, the standard length suffix and
+/// a fold width of 16, giving a 16-byte dense hash.
+///
+/// Lengths larger than the ring size are invalid under the algorithm, and are skipped.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct KnotHashParams {
+    ring_size: usize,
+    rounds: usize,
+    suffix: Cow<'static, [u8]>,
+    fold_width: usize,
+}
+
+impl Default for KnotHashParams {
+    fn default() -> Self {
+        Self {
+            ring_size: 256,
+            rounds: 64,
+            suffix: Cow::Borrowed(&LENGTH_SUFFIX),
+            fold_width: 16,
+        }
+    }
+}
+
+impl KnotHashParams {
+    /// Creates a new set of parameters, checking that the ring size is between 1 and 256 and is a
+    /// multiple of the fold width.
+    pub fn new(ring_size: usize, rounds: usize, suffix: &[u8], fold_width: usize) -> Result<Self, KnotHashParamsError> {
+        if ring_size == 0 {
+            return Err(KnotHashParamsError::EmptyRing);
+        }
+        if ring_size > 256 {
+            return Err(KnotHashParamsError::RingTooLarge(ring_size));
+        }
+        if fold_width == 0 {
+            return Err(KnotHashParamsError::ZeroFoldWidth);
+        }
+        if !ring_size.is_multiple_of(fold_width) {
+            return Err(KnotHashParamsError::FoldWidthMismatch { ring_size, fold_width });
+        }
+        return Ok(Self {
+            ring_size,
+            rounds,
+            suffix: Cow::Owned(suffix.to_vec()),
+            fold_width,
+        });
+    }
+
+    pub fn ring_size(&self) -> usize {
+        return self.ring_size;
+    }
+
+    pub fn rounds(&self) -> usize {
+        return self.rounds;
+    }
+
+    /// Returns the lengths appended to the input before the rounds are performed.
+    pub fn suffix(&self) -> &[u8] {
+        return &self.suffix;
+    }
+
+    /// Returns the number of consecutive ring elements XOR'd together into each byte of the dense
+    /// hash.
+    pub fn fold_width(&self) -> usize {
+        return self.fold_width;
+    }
+
+    /// Returns the number of bytes in the dense hash produced with these parameters.
+    pub fn output_len(&self) -> usize {
+        return self.ring_size / self.fold_width;
+    }
+
+    /// Calculates the sparse hash of the given lengths, being the state of the ring after each of
+    /// the rounds has been performed using the lengths followed by the suffix.
+    pub fn sparse_hash(&self, lengths: &[u8]) -> Vec<u8> {
+        let mut ring = [0u8; 256];
+        self.calculate_ring(lengths, &mut ring);
+        return ring[..self.ring_size].to_vec();
+    }
+
+    /// Calculates the dense hash of the given input, folding the sparse hash into blocks of the
+    /// fold width.
+    pub fn hash(&self, input: &[u8]) -> Vec<u8> {
+        let mut ring = [0u8; 256];
+        self.calculate_ring(input, &mut ring);
+        let mut dense_hash = vec![0u8; self.output_len()];
+        calculate_dense_hash(&ring[..self.ring_size], &mut dense_hash);
+        return dense_hash;
+    }
+
+    /// Performs the rounds of the sparse hash in place, using the leading elements of the given
+    /// ring buffer.
+    fn calculate_ring(&self, lengths: &[u8], ring: &mut [u8; 256]) {
+        let ring = &mut ring[..self.ring_size];
+        // Initialise ring, current position and skip size
+        for (i, value) in ring.iter_mut().enumerate() {
+            *value = i as u8;
+        }
+        let mut current_pos: usize = 0;
+        let mut skip_size: usize = 0;
+        for _ in 0..self.rounds {
+            knot_round(ring, lengths, &mut current_pos, &mut skip_size);
+            knot_round(ring, &self.suffix, &mut current_pos, &mut skip_size);
+        }
+    }
+}
+
+/// Calculates the knot hash of arbitrary bytes using the algorithm developed in AoC 2017 Day 10,
+/// Part 2. Input can be provided incrementally over any number of calls to update.
+///
+/// Every round of the sparse hash is applied to the complete input, so the hasher buffers the input
+/// given to update and performs the rounds when finalize is called.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct KnotHasher {
+    input: Vec<u8>,
+}
+
+impl KnotHasher {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Appends the given bytes to the input of the hash.
+    pub fn update(&mut self, data: &[u8]) {
+        self.input.extend_from_slice(data);
+    }
+
+    /// Consumes the hasher, returning the 16-byte dense hash of all input provided.
+    pub fn finalize(self) -> [u8; 16] {
+        return self.calculate();
+    }
+
+    /// Calculates the dense hash of all input provided so far, using the default parameters.
+    fn calculate(&self) -> [u8; 16] {
+        let mut ring = [0u8; 256];
+        KnotHashParams::default().calculate_ring(&self.input, &mut ring);
+        let mut digest = [0u8; 16];
+        calculate_dense_hash(&ring, &mut digest);
+        return digest;
+    }
+}
+
+impl Hasher for KnotHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+
+    /// Returns the first 8 bytes of the dense hash of the input written so far, read as a big-endian
+    /// integer. The full hash is recalculated on each call.
+    fn finish(&self) -> u64 {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&self.calculate()[..8]);
+        return u64::from_be_bytes(bytes);
+    }
+}
+
+impl OutputSizeUser for KnotHasher {
+    type OutputSize = U16;
+}
+
+impl Update for KnotHasher {
+    fn update(&mut self, data: &[u8]) {
+        self.input.extend_from_slice(data);
+    }
+}
+
+impl FixedOutput for KnotHasher {
+    fn finalize_into(self, out: &mut Output<Self>) {
+        out.copy_from_slice(&self.calculate());
+    }
+}
+
+impl Reset for KnotHasher {
+    fn reset(&mut self) {
+        self.input.clear();
+    }
+}
+
+impl FixedOutputReset for KnotHasher {
+    fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+        out.copy_from_slice(&self.calculate());
+        self.input.clear();
+    }
+}
+
+impl HashMarker for KnotHasher {}
+
+/// Builds KnotHasher instances, allowing the knot hash to be used as the hasher of a HashMap or
+/// HashSet.
+pub type KnotHashBuildHasher = BuildHasherDefault<KnotHasher>;
+
 /// Calculates the knot hash of the given input using the algorithm developed in in AoC 2017 Day 10,
-/// Part 2.
-pub fn calculate_knot_hash(input: &str) -> Vec<u8> {
-    // Read input in as ASCII characters and convert into array of lengths for processing
-    let mut lengths = input.chars().map(|x| x as u8).collect::<Vec<u8>>();
-    lengths.append(&mut vec![17, 31, 73, 47, 23]);
-    // Initialise list, current position and skip size
-    let mut list = (0..=255).collect::<Vec<u8>>();
-    let mut current_pos: usize = 0;
-    let mut skip_size: usize = 0;
-    // Perform 64 iterations of sparse hash calculation
-    for _ in 0..64 {
-        list = calculate_sparse_hash(&list, &lengths, &mut current_pos, &mut skip_size);
-    }
-    // Calculate dense hash
-    let dense_hash = calculate_dense_hash(&list);
-    return dense_hash;
+/// Part 2. The input is hashed as its UTF-8 bytes.
+pub fn calculate_knot_hash(input: &str) -> KnotDigest {
+    let mut hasher = KnotHasher::new();
+    hasher.update(input.as_bytes());
+    return KnotDigest::from(hasher.finalize());
+}
+
+/// Performs a single round of the sparse hash (first stage of knot hash) algorithm in place,
+/// reversing each span of the ring without allocating. Lengths larger than the ring are skipped.
+pub fn knot_round(ring: &mut [u8], lengths: &[u8], current_pos: &mut usize, skip_size: &mut usize) {
+    let ring_size = ring.len();
+    if ring_size == 0 {
+        return;
+    }
+    for length in lengths {
+        let length = *length as usize;
+        if length > ring_size {
+            continue;
+        }
+        // Swap values from both ends of the span inwards, wrapping around the end of the ring
+        let mut start = *current_pos;
+        let mut end = (*current_pos + length + ring_size - 1) % ring_size;
+        for _ in 0..length / 2 {
+            ring.swap(start, end);
+            start = if start + 1 == ring_size { 0 } else { start + 1 };
+            end = if end == 0 { ring_size - 1 } else { end - 1 };
+        }
+        *current_pos = (*current_pos + length + *skip_size) % ring_size;
+        *skip_size += 1;
+    }
 }
 
 /// Performs a single round of the sparse hash (first stage of knot hash) algorithm introduced in
-/// AoC 2017 Day 10, Part 1.
+/// AoC 2017 Day 10, Part 1, returning the updated list. Allocates for every length processed, so
+/// knot_round should be preferred where performance matters.
 pub fn calculate_sparse_hash(
-    list: &Vec<u8>,
-    lengths: &Vec<u8>,
+    list: &[u8],
+    lengths: &[u8],
     current_pos: &mut usize,
     skip_size: &mut usize,
 ) -> Vec<u8> {
-    let mut sparse_hash = list.clone();
+    let mut sparse_hash = list.to_vec();
     for length in lengths {
         let length = *length as usize;
         // Calculate list of indices included in the reverse
         let mut rev_indices = Vec::<usize>::new();
         for i in 0..length {
             let index = (*current_pos + i) % sparse_hash.len();
-            &rev_indices.push(index);
+            rev_indices.push(index);
         }
         // Extract values from the list then reverse
         let mut extract_list = Vec::<u8>::new();
@@ -54,17 +301,98 @@ pub fn calculate_sparse_hash(
 }
 
 /// Calculates the dense hash of the output from the sparse hash calculation phase of the knot hash
-/// algorithm. Introduced in AoC 2017 Day 10, Part 2.
-fn calculate_dense_hash(list: &Vec<u8>) -> Vec<u8> {
-    let mut dense_hash: Vec<u8> = vec![0; 16];
-    for group in 0..16 {
-        let start = group * 16;
-        // Calculate the result of XOR'ing the 16 values in the current group
-        let mut xor_res: u8 = 0;
-        for i in 0..16 {
-            xor_res ^= list[start + i];
+/// algorithm, XOR'ing together equal blocks of the list into each byte of the output. Introduced in
+/// AoC 2017 Day 10, Part 2.
+fn calculate_dense_hash(list: &[u8], dense_hash: &mut [u8]) {
+    let fold_width = list.len() / dense_hash.len();
+    for (byte, block) in dense_hash.iter_mut().zip(list.chunks(fold_width)) {
+        *byte = block.iter().fold(0, |acc, value| acc ^ value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_knot_hash_examples() {
+        assert_eq!("a2582a3a0e66e6e86e3812dcb672a272", calculate_knot_hash("").to_hex());
+        assert_eq!("33efeb34ea91902bb2f59c9920caa6cd", calculate_knot_hash("AoC 2017").to_hex());
+        assert_eq!("3efbe78a8d82f29979031a4aa0b16a9d", calculate_knot_hash("1,2,3").to_hex());
+        assert_eq!("63960835bcdc130f0b66d7ff4f6a5a8e", calculate_knot_hash("1,2,4").to_hex());
+    }
+
+    #[test]
+    fn test_knot_hash_params() {
+        // Worked example from Day 10, Part 1
+        let params = KnotHashParams::new(5, 1, &[], 5).unwrap();
+        assert_eq!(vec![3, 4, 2, 1, 0], params.sparse_hash(&[3, 4, 1, 5]));
+        // Lengths longer than the ring are skipped
+        assert_eq!(vec![3, 4, 2, 1, 0], params.sparse_hash(&[3, 4, 1, 6, 5]));
+        assert_eq!(vec![3 ^ 4 ^ 2 ^ 1], params.hash(&[3, 4, 1, 5]));
+        assert_eq!(16, KnotHashParams::default().output_len());
+        assert_eq!(calculate_knot_hash("1,2,3").as_ref(), KnotHashParams::default().hash(b"1,2,3"));
+        assert_eq!(Err(KnotHashParamsError::EmptyRing), KnotHashParams::new(0, 1, &[], 1));
+        assert_eq!(Err(KnotHashParamsError::RingTooLarge(257)), KnotHashParams::new(257, 1, &[], 1));
+        assert_eq!(Err(KnotHashParamsError::ZeroFoldWidth), KnotHashParams::new(256, 64, &[], 0));
+        assert_eq!(
+            Err(KnotHashParamsError::FoldWidthMismatch { ring_size: 10, fold_width: 4 }),
+            KnotHashParams::new(10, 1, &[], 4)
+        );
+    }
+
+    #[test]
+    fn test_knot_round_matches_sparse_hash() {
+        let lengths = b"flqrgnkx-127\x11\x1f\x49\x2f\x17";
+        let mut expected = (0..=255).collect::<Vec<u8>>();
+        let mut ring = [0u8; 256];
+        for (i, value) in ring.iter_mut().enumerate() {
+            *value = i as u8;
         }
-        dense_hash[group] = xor_res;
+        let (mut pos_1, mut skip_1, mut pos_2, mut skip_2) = (0, 0, 0, 0);
+        for _ in 0..64 {
+            expected = calculate_sparse_hash(&expected, lengths, &mut pos_1, &mut skip_1);
+            knot_round(&mut ring, lengths, &mut pos_2, &mut skip_2);
+        }
+        assert_eq!(expected, ring.to_vec());
+        assert_eq!((pos_1, skip_1), (pos_2, skip_2));
+        // Empty ring is left untouched rather than dividing by its size
+        let (mut current_pos, mut skip_size) = (0, 0);
+        knot_round(&mut [], &[0, 1], &mut current_pos, &mut skip_size);
+        assert_eq!((0, 0), (current_pos, skip_size));
+    }
+
+    #[test]
+    fn test_knot_hasher_incremental_update() {
+        let mut hasher = KnotHasher::new();
+        hasher.update(b"AoC");
+        hasher.update(b"");
+        hasher.update(b" 2017");
+        assert_eq!(calculate_knot_hash("AoC 2017"), KnotDigest::from(hasher.finalize()));
+        // Non-ASCII input is hashed as UTF-8 rather than truncated
+        assert_ne!(calculate_knot_hash("\u{141}"), calculate_knot_hash("A"));
+    }
+
+    #[test]
+    fn test_knot_hasher_trait_impls() {
+        use digest::Digest;
+        use std::collections::HashMap;
+
+        let expected = calculate_knot_hash("AoC 2017").as_bytes().to_vec();
+        assert_eq!(expected, <KnotHasher as Digest>::digest(b"AoC 2017").to_vec());
+        let mut hasher = <KnotHasher as Digest>::new();
+        Digest::update(&mut hasher, b"discarded");
+        Digest::reset(&mut hasher);
+        Digest::update(&mut hasher, b"AoC 2017");
+        assert_eq!(expected, hasher.finalize_reset().to_vec());
+        assert_eq!(calculate_knot_hash("").as_bytes().to_vec(), hasher.finalize().to_vec());
+        // std Hasher reports the leading bytes of the dense hash
+        let mut hasher = KnotHasher::new();
+        hasher.write(b"AoC 2017");
+        assert_eq!(0x33efeb34ea91902b, hasher.finish());
+        let mut map = HashMap::<&str, usize, KnotHashBuildHasher>::default();
+        map.insert("knot", 10);
+        map.insert("hash", 14);
+        assert_eq!(Some(&14), map.get("hash"));
     }
-    return dense_hash;
 }
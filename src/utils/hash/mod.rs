@@ -1,3 +1,5 @@
+mod knot_digest;
 mod knot_hash;
 
+pub use self::knot_digest::*;
 pub use self::knot_hash::*;
\ No newline at end of file
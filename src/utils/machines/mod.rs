@@ -1,4 +1,38 @@
 mod soundcomp;
+mod soundcomp_analysis;
+mod soundcomp_builder;
+mod soundcomp_compiled;
+mod soundcomp_debugger;
+mod soundcomp_disasm;
+mod soundcomp_error;
+#[cfg(test)]
+mod soundcomp_fuzz;
+mod soundcomp_io;
+mod soundcomp_network;
+mod soundcomp_optimise;
+mod soundcomp_parser;
+mod soundcomp_profile;
+mod soundcomp_replay;
+mod soundcomp_trace;
 
+pub use self::soundcomp::Backend;
+pub use self::soundcomp::Opcode;
+pub use self::soundcomp::Operand;
+pub use self::soundcomp::OverflowPolicy;
+pub use self::soundcomp::RunUntil;
 pub use self::soundcomp::SoundComputer;
 pub use self::soundcomp::SoundComputerInstruction;
+pub use self::soundcomp::SoundComputerState;
+pub use self::soundcomp::StopReason;
+pub use self::soundcomp_analysis::*;
+pub use self::soundcomp_builder::*;
+pub use self::soundcomp_debugger::*;
+pub use self::soundcomp_disasm::*;
+pub use self::soundcomp_error::*;
+pub use self::soundcomp_io::*;
+pub use self::soundcomp_network::*;
+pub use self::soundcomp_optimise::*;
+pub use self::soundcomp_parser::*;
+pub use self::soundcomp_profile::{LoopProfile, ProfileReport};
+pub use self::soundcomp_replay::*;
+pub use self::soundcomp_trace::*;
@@ -1,134 +1,631 @@
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::VecDeque;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
-#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+use enum_iterator::IntoEnumIterator;
+
+use std::sync::{Arc, Mutex};
+
+use super::soundcomp_builder::SoundComputerBuilder;
+use super::soundcomp_compiled::{compile_instruction, register_index, CompiledOp, CompiledProgram, JumpDest, Slot, REGISTER_COUNT};
+use super::soundcomp_error::{MachineError, MachineErrorKind};
+use super::soundcomp_io::{IoPolicy, QueueIo, Receive, SoundIo};
+use super::soundcomp_optimise::FusedKernel;
+use super::soundcomp_profile::{ProfileReport, Profiler};
+use super::soundcomp_trace::{ExecutionObserver, RegisterDelta};
+
+/// Represents a single operand of a SoundComputerInstruction, resolved when the program is loaded
+/// into either a register name or an immediate value.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum Operand {
+    Register(char),
+    Value(i64),
+}
+
+/// Identifies the operation performed by a SoundComputerInstruction, independent of its operands.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, IntoEnumIterator)]
+pub enum Opcode {
+    Snd,
+    Set,
+    Add,
+    Sub,
+    Mul,
+    Mod,
+    Rcv,
+    Jgz,
+    Jnz,
+    Fused,
+}
+
+impl Opcode {
+    /// Returns the mnemonic used for the opcode in SoundComputer source text.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Opcode::Snd => return "snd",
+            Opcode::Set => return "set",
+            Opcode::Add => return "add",
+            Opcode::Sub => return "sub",
+            Opcode::Mul => return "mul",
+            Opcode::Mod => return "mod",
+            Opcode::Rcv => return "rcv",
+            Opcode::Jgz => return "jgz",
+            Opcode::Jnz => return "jnz",
+            Opcode::Fused => return "fused",
+        }
+    }
+}
+
+/// Determines how the SoundComputer handles arithmetic results that do not fit in a 64-bit register.
+///
+/// The mod instruction always computes the truncated remainder, taking the sign of the dividend, so
+/// a negative divisor gives the same result as its absolute value. Division by zero is a fault under
+/// the checked policy, and leaves the register value unchanged under the other policies.
+///
+/// Registers are always 64-bit, so there is no big-integer policy. Unbounded registers would change
+/// the register type across the queues, the IoPolicy interface and the compiled backend.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum OverflowPolicy {
+    /// Overflow halts the SoundComputer with a MachineError.
+    Checked,
+    /// Results wrap around at the boundary of the register type.
+    Wrapping,
+    /// Results are clamped to the minimum or maximum register value.
+    Saturating,
+}
+
+impl OverflowPolicy {
+    /// Calculates the result of applying the arithmetic opcode to the given values under the policy.
+    fn apply(&self, opcode: Opcode, left: i64, right: i64) -> Result<i64, MachineErrorKind> {
+        if opcode == Opcode::Mod && right == 0 {
+            match self {
+                OverflowPolicy::Checked => return Err(MachineErrorKind::DivisionByZero),
+                _ => return Ok(left),
+            }
+        }
+        let result = match (self, opcode) {
+            (OverflowPolicy::Checked, Opcode::Add) => left.checked_add(right),
+            (OverflowPolicy::Checked, Opcode::Sub) => left.checked_sub(right),
+            (OverflowPolicy::Checked, Opcode::Mul) => left.checked_mul(right),
+            (OverflowPolicy::Wrapping, Opcode::Add) => Some(left.wrapping_add(right)),
+            (OverflowPolicy::Wrapping, Opcode::Sub) => Some(left.wrapping_sub(right)),
+            (OverflowPolicy::Wrapping, Opcode::Mul) => Some(left.wrapping_mul(right)),
+            (OverflowPolicy::Saturating, Opcode::Add) => Some(left.saturating_add(right)),
+            (OverflowPolicy::Saturating, Opcode::Sub) => Some(left.saturating_sub(right)),
+            (OverflowPolicy::Saturating, Opcode::Mul) => Some(left.saturating_mul(right)),
+            // Only i64::MIN % -1 overflows, with the true remainder being zero under every policy
+            (_, Opcode::Mod) => Some(left.wrapping_rem(right)),
+            _ => panic!("Opcode {:?} is not an arithmetic opcode!", opcode),
+        };
+        return result.ok_or(MachineErrorKind::ArithmeticOverflow);
+    }
+}
+
+/// Execution engine used by the SoundComputer to run instructions.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum Backend {
+    /// Reference interpreter, executing the SoundComputerInstruction list directly.
+    Interpreter,
+    /// Program compiled to use a dense register array and pre-resolved jump targets. Only used by
+    /// SoundComputer::run_until when no observer is attached and profiling is disabled, with single
+    /// steps and programs using registers outside of 'a' to 'z' always using the interpreter.
+    Compiled,
+}
+
+/// Instructions executed by the SoundComputer. Covers the instruction set used by the sound
+/// computer in AoC 2017 Day 18, plus the "sub" and "jnz" instructions used by the coprocessor in
+/// AoC 2017 Day 23. Fused instructions are only generated by the peephole optimiser, and have no
+/// source text form.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
 pub enum SoundComputerInstruction {
-    Snd { value_1: String },
-    Set { value_1: String, value_2: String },
-    Add { value_1: String, value_2: String },
-    Mul { value_1: String, value_2: String },
-    Mod { value_1: String, value_2: String },
-    Rcv { value_1: String },
-    Jgz { value_1: String, value_2: String },
+    Snd { value_1: Operand },
+    Set { value_1: Operand, value_2: Operand },
+    Add { value_1: Operand, value_2: Operand },
+    Sub { value_1: Operand, value_2: Operand },
+    Mul { value_1: Operand, value_2: Operand },
+    Mod { value_1: Operand, value_2: Operand },
+    Rcv { value_1: Operand },
+    Jgz { value_1: Operand, value_2: Operand },
+    Jnz { value_1: Operand, value_2: Operand },
+    Fused { kernel: FusedKernel },
+}
+
+impl SoundComputerInstruction {
+    /// Returns the opcode of the instruction.
+    pub fn opcode(&self) -> Opcode {
+        match self {
+            SoundComputerInstruction::Snd { .. } => return Opcode::Snd,
+            SoundComputerInstruction::Set { .. } => return Opcode::Set,
+            SoundComputerInstruction::Add { .. } => return Opcode::Add,
+            SoundComputerInstruction::Sub { .. } => return Opcode::Sub,
+            SoundComputerInstruction::Mul { .. } => return Opcode::Mul,
+            SoundComputerInstruction::Mod { .. } => return Opcode::Mod,
+            SoundComputerInstruction::Rcv { .. } => return Opcode::Rcv,
+            SoundComputerInstruction::Jgz { .. } => return Opcode::Jgz,
+            SoundComputerInstruction::Jnz { .. } => return Opcode::Jnz,
+            SoundComputerInstruction::Fused { .. } => return Opcode::Fused,
+        }
+    }
 }
 
-pub struct SoundComputer {
+/// Snapshot of the complete execution state of a SoundComputer, excluding the program itself and
+/// any attached observer or profiler. Snapshots include the send, receive and opcode counters, so
+/// two snapshots taken after different numbers of steps never compare equal. Cycle detection should
+/// compare the snapshots returned by SoundComputerState::without_counters instead.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct SoundComputerState<P = QueueIo> {
+    prog_c: usize,
+    registers: BTreeMap<char, i64>,
     input_queue: VecDeque<i64>,
     output_queue: VecDeque<i64>,
-    single_mode_freqs_played: VecDeque<i64>,
-    recovered_freqs: VecDeque<i64>,
+    io: P,
+    snd_count: usize,
+    rcv_count: usize,
+    opcode_counts: Vec<(Opcode, usize)>,
+    halted: bool,
+    fault: Option<MachineError>,
+    awaiting_input: bool,
+}
+
+impl<P> SoundComputerState<P> {
+    pub fn prog_c(&self) -> usize {
+        return self.prog_c;
+    }
+
+    /// Returns the register values captured in the snapshot, in register name order.
+    pub fn registers(&self) -> &BTreeMap<char, i64> {
+        return &self.registers;
+    }
+
+    pub fn input_queue(&self) -> &VecDeque<i64> {
+        return &self.input_queue;
+    }
+
+    pub fn output_queue(&self) -> &VecDeque<i64> {
+        return &self.output_queue;
+    }
+
+    /// Returns the state of the IoPolicy captured in the snapshot.
+    pub fn io(&self) -> &P {
+        return &self.io;
+    }
+
+    pub fn snd_count(&self) -> usize {
+        return self.snd_count;
+    }
+
+    pub fn rcv_count(&self) -> usize {
+        return self.rcv_count;
+    }
+
+    pub fn is_halted(&self) -> bool {
+        return self.halted;
+    }
+
+    pub fn is_awaiting_input(&self) -> bool {
+        return self.awaiting_input;
+    }
+
+    pub fn fault(&self) -> Option<&MachineError> {
+        return self.fault.as_ref();
+    }
+}
+
+impl<P: Clone> SoundComputerState<P> {
+    /// Returns a copy of the snapshot with the send, receive and opcode counters cleared. Copies
+    /// made from snapshots of the same execution state compare and hash equal, no matter how many
+    /// steps were executed in between.
+    pub fn without_counters(&self) -> Self {
+        return Self {
+            snd_count: 0,
+            rcv_count: 0,
+            opcode_counts: vec![],
+            ..self.clone()
+        };
+    }
+}
+
+/// Event that SoundComputer::run_until executes until, in addition to halting or blocking on input.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum RunUntil {
+    /// Run until the SoundComputer halts or blocks on input.
+    Block,
+    /// Run until an snd instruction is executed.
+    Output,
+    /// Run until an rcv instruction receives a value, or recovers a frequency under SoundIo.
+    Recover,
+}
+
+/// Reason that SoundComputer::run_until stopped executing instructions.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum StopReason {
+    Halted,
+    AwaitingInput,
+    Output,
+    Recovered,
+    BudgetExhausted,
+}
+
+/// Machine executing SoundComputerInstruction programs, with the semantics of the snd and rcv
+/// instructions determined by its IoPolicy.
+pub struct SoundComputer<P = QueueIo> {
+    input_queue: VecDeque<i64>,
+    output_queue: VecDeque<i64>,
+    io: P,
     prog_c: usize,
     halted: bool,
     instructions: Vec<SoundComputerInstruction>,
     registers: HashMap<char, i64>,
     snd_count: usize,
     rcv_count: usize,
-    awaiting_input: bool
+    opcode_counts: HashMap<Opcode, usize>,
+    profiler: Option<Profiler>,
+    observer: Option<Arc<Mutex<dyn ExecutionObserver + Send>>>,
+    overflow_policy: OverflowPolicy,
+    fault: Option<MachineError>,
+    awaiting_input: bool,
+    backend: Backend,
+    compiled: Option<Arc<CompiledProgram>>,
+    max_steps: Option<usize>
+}
+
+/// Clones of a SoundComputer do not inherit its observer, so a forked machine never reports its
+/// steps to the observer of the machine it was forked from.
+impl<P: Clone> Clone for SoundComputer<P> {
+    fn clone(&self) -> Self {
+        Self {
+            input_queue: self.input_queue.clone(),
+            output_queue: self.output_queue.clone(),
+            io: self.io.clone(),
+            prog_c: self.prog_c,
+            halted: self.halted,
+            instructions: self.instructions.clone(),
+            registers: self.registers.clone(),
+            snd_count: self.snd_count,
+            rcv_count: self.rcv_count,
+            opcode_counts: self.opcode_counts.clone(),
+            profiler: self.profiler.clone(),
+            observer: None,
+            overflow_policy: self.overflow_policy,
+            fault: self.fault,
+            awaiting_input: self.awaiting_input,
+            backend: self.backend,
+            compiled: self.compiled.clone(),
+            max_steps: self.max_steps,
+        }
+    }
+}
+
+/// Returns the name of the register targeted by the given destination operand. An immediate value
+/// is rejected, as there is no register to write the result into.
+fn destination_register(operand: &Operand) -> Result<char, MachineErrorKind> {
+    match operand {
+        Operand::Register(reg) => return Ok(*reg),
+        Operand::Value(value) => return Err(MachineErrorKind::ImmediateDestination(*value)),
+    }
 }
 
-/// Checks if the given string is a register name
-fn is_reg_name(input: &String) -> bool {
-    return !input.parse::<i64>().is_ok();
+/// Returns the names of the registers the given instruction may write a new value into.
+fn written_registers(instruction: &SoundComputerInstruction) -> Vec<char> {
+    match instruction {
+        SoundComputerInstruction::Set{value_1: Operand::Register(reg), value_2:_} |
+        SoundComputerInstruction::Add{value_1: Operand::Register(reg), value_2:_} |
+        SoundComputerInstruction::Sub{value_1: Operand::Register(reg), value_2:_} |
+        SoundComputerInstruction::Mul{value_1: Operand::Register(reg), value_2:_} |
+        SoundComputerInstruction::Mod{value_1: Operand::Register(reg), value_2:_} |
+        SoundComputerInstruction::Rcv{value_1: Operand::Register(reg)} => return vec![*reg],
+        SoundComputerInstruction::Fused{kernel} => return kernel.registers(),
+        _ => return vec![],
+    }
 }
 
 impl SoundComputer {
-    pub fn new(instructions: &Vec<SoundComputerInstruction>) -> Self {
-        let reg_state = SoundComputer::gen_initial_reg_state(instructions);
+    /// Creates a new SoundComputer using the send and receive semantics of QueueIo.
+    pub fn new(instructions: &[SoundComputerInstruction]) -> Self {
+        return SoundComputer::with_io(instructions, QueueIo);
+    }
+
+    /// Returns a builder used to configure the initial state of a new SoundComputer.
+    pub fn builder() -> SoundComputerBuilder {
+        return SoundComputerBuilder::new();
+    }
+
+    /// Executes a single instruction, returning the value sent by the instruction if one was queued
+    /// for output.
+    pub fn execute_double_mode(&mut self) -> Result<Option<i64>, MachineError> {
+        self.step()?;
+        return Ok(self.output_queue.pop_front());
+    }
+}
+
+impl SoundComputer<SoundIo> {
+    /// Returns the last frequency that was recovered by the SoundComputer.
+    pub fn last_recovered_freq(&self) -> Option<i64> {
+        return self.io.last_recovered();
+    }
+
+    /// Executes the SoundComputer until the first frequency is recovered or the SoundComputer halts.
+    pub fn execute_single_mode(&mut self) -> Result<(), MachineError> {
+        // Check if we have already reached the break condition for single mode execution
+        if self.rcv_count >= 1 {
+            return Ok(());
+        }
+        self.run_until(RunUntil::Recover, None)?;
+        return Ok(());
+    }
+}
+
+impl<P: IoPolicy> SoundComputer<P> {
+    /// Creates a new SoundComputer using the given IoPolicy for the snd and rcv instructions.
+    pub fn with_io(instructions: &[SoundComputerInstruction], io: P) -> Self {
+        let reg_state = SoundComputer::<P>::gen_initial_reg_state(instructions);
         Self {
             input_queue: VecDeque::new(),
             output_queue: VecDeque::new(),
-            single_mode_freqs_played: VecDeque::new(),
-            recovered_freqs: VecDeque::new(),
+            io,
             prog_c: 0,
             halted: false,
             instructions: instructions.to_vec(),
             registers: reg_state,
             snd_count: 0,
             rcv_count: 0,
-            awaiting_input: false
+            opcode_counts: HashMap::new(),
+            profiler: None,
+            observer: None,
+            overflow_policy: OverflowPolicy::Wrapping,
+            fault: None,
+            awaiting_input: false,
+            backend: Backend::Interpreter,
+            compiled: None,
+            max_steps: None
         }
     }
 
+    /// Returns the IoPolicy determining the semantics of the snd and rcv instructions.
+    pub fn io(&self) -> &P {
+        return &self.io;
+    }
+
+    pub fn io_mut(&mut self) -> &mut P {
+        return &mut self.io;
+    }
+
     /// Updates the specified register to the given value. If the register was not previously
     /// present in the SoundComputer, it is added and initialised to the given value.
     pub fn update_register(&mut self, reg: char, value: i64) {
         self.registers.insert(reg, value);
     }
 
-    /// Returns the last frequency that was recovered by the SoundComputer, when executing in
-    /// single-mode.
-    pub fn last_recovered_freq(&self) -> Option<&i64> {
-        return self.recovered_freqs.back();
+    /// Returns the current value of the given register, or None if the register is not present in
+    /// the SoundComputer.
+    pub fn register(&self, reg: char) -> Option<i64> {
+        return self.registers.get(&reg).copied();
     }
 
-    /// Evaluates the provided value, depending on if it is a register name (single lowercase letter),
-    /// or a raw value.
-    fn evaluate_instruction_args_one_value(&self, value_1: &String) -> (Option<i64>, Option<i64>) {
-        let val_1 = {
-            if is_reg_name(value_1) {
-                *self.registers.get(&value_1.chars().next().unwrap()).unwrap()
-            } else {
-                value_1.parse::<i64>().unwrap()
-            }
-        };
-        return (Some(val_1), None);
+    /// Returns an iterator over the name and current value of every register, in register name
+    /// order.
+    pub fn registers(&self) -> impl Iterator<Item = (char, i64)> {
+        let mut registers = self.registers.iter().map(|(k, v)| (*k, *v)).collect::<Vec<(char, i64)>>();
+        registers.sort_unstable();
+        return registers.into_iter();
     }
 
-    /// Evaluates the provided values, depending on if they are register names (single lowercase
-    /// letter) or a raw value.
-    fn evaluate_instruction_args_two_values(&self, value_1: &String, value_2: &String) -> (Option<i64>, Option<i64>) {
-        let val_1 = {
-            if is_reg_name(value_1) {
-                *self.registers.get(&value_1.chars().next().unwrap()).unwrap()
-            } else {
-                value_1.parse::<i64>().unwrap()
-            }
-        };
-        let val_2 = {
-            if is_reg_name(value_2) {
-                *self.registers.get(&value_2.chars().next().unwrap()).unwrap()
-            } else {
-                value_2.parse::<i64>().unwrap()
-            }
+    /// Returns the current value of the program counter.
+    pub fn prog_c(&self) -> usize {
+        return self.prog_c;
+    }
+
+    /// Returns the instruction at the program counter, or None if the program counter is outside of
+    /// the instruction list.
+    pub fn current_instruction(&self) -> Option<&SoundComputerInstruction> {
+        return self.instructions.get(self.prog_c);
+    }
+
+    /// Returns the instructions loaded into the SoundComputer.
+    pub fn instructions(&self) -> &[SoundComputerInstruction] {
+        return &self.instructions;
+    }
+
+    /// Returns the values waiting in the input queue, front first.
+    pub fn input_queue(&self) -> &VecDeque<i64> {
+        return &self.input_queue;
+    }
+
+    /// Returns the values waiting in the output queue, front first.
+    pub fn output_queue(&self) -> &VecDeque<i64> {
+        return &self.output_queue;
+    }
+
+    /// Removes all values waiting in the input queue.
+    pub fn clear_input(&mut self) {
+        self.input_queue.clear();
+    }
+
+    /// Removes all values waiting in the output queue.
+    pub fn clear_output(&mut self) {
+        self.output_queue.clear();
+    }
+
+    /// Sets the policy used to handle arithmetic overflow. Defaults to OverflowPolicy::Wrapping.
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        return self.overflow_policy;
+    }
+
+    /// Sets the backend used to execute instructions, compiling the program if required. Defaults
+    /// to Backend::Interpreter.
+    pub fn set_backend(&mut self, backend: Backend) {
+        if backend == Backend::Compiled && self.compiled.is_none() {
+            self.compiled = CompiledProgram::compile(&self.instructions).map(Arc::new);
+        }
+        self.backend = backend;
+    }
+
+    pub fn backend(&self) -> Backend {
+        return self.backend;
+    }
+
+    /// Sets the maximum number of instructions executed by each call to SoundComputer::run, or
+    /// removes the limit if None is given. Defaults to no limit.
+    pub fn set_max_steps(&mut self, max_steps: Option<usize>) {
+        self.max_steps = max_steps;
+    }
+
+    pub fn max_steps(&self) -> Option<usize> {
+        return self.max_steps;
+    }
+
+    /// Returns the fault that halted the SoundComputer, if one has occurred.
+    pub fn fault(&self) -> Option<&MachineError> {
+        return self.fault.as_ref();
+    }
+
+    /// Captures a snapshot of the current execution state of the SoundComputer.
+    pub fn snapshot(&self) -> SoundComputerState<P> {
+        return SoundComputerState {
+            prog_c: self.prog_c,
+            registers: self.registers.iter().map(|(k, v)| (*k, *v)).collect(),
+            input_queue: self.input_queue.clone(),
+            output_queue: self.output_queue.clone(),
+            io: self.io.clone(),
+            snd_count: self.snd_count,
+            rcv_count: self.rcv_count,
+            opcode_counts: self.opcode_counts(),
+            halted: self.halted,
+            fault: self.fault,
+            awaiting_input: self.awaiting_input,
         };
-        return (Some(val_1), Some(val_2));
+    }
+
+    /// Calculates a hash of the execution state of the SoundComputer, excluding the send, receive and
+    /// opcode counters. A Network uses the hash to find repeated states cheaply when detecting
+    /// livelock. Equal hashes can also be caused by a collision, so a repeat should be confirmed by
+    /// comparing the snapshots returned by SoundComputerState::without_counters.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.prog_c.hash(&mut hasher);
+        for register in self.registers() {
+            register.hash(&mut hasher);
+        }
+        self.input_queue.hash(&mut hasher);
+        self.output_queue.hash(&mut hasher);
+        self.io.hash(&mut hasher);
+        self.halted.hash(&mut hasher);
+        self.fault.hash(&mut hasher);
+        self.awaiting_input.hash(&mut hasher);
+        return hasher.finish();
+    }
+
+    /// Restores the SoundComputer to the execution state captured in the given snapshot. The
+    /// snapshot is expected to have been taken from a SoundComputer running the same program.
+    pub fn restore(&mut self, state: &SoundComputerState<P>) {
+        self.prog_c = state.prog_c;
+        self.registers = state.registers.iter().map(|(k, v)| (*k, *v)).collect();
+        self.input_queue = state.input_queue.clone();
+        self.output_queue = state.output_queue.clone();
+        self.io = state.io.clone();
+        self.snd_count = state.snd_count;
+        self.rcv_count = state.rcv_count;
+        self.opcode_counts = state.opcode_counts.iter().filter(|(_, v)| *v > 0).copied().collect();
+        self.halted = state.halted;
+        self.fault = state.fault;
+        self.awaiting_input = state.awaiting_input;
+    }
+
+    /// Evaluates the given operand, returning either the current value of the register it names or
+    /// its immediate value.
+    fn evaluate_operand(&self, operand: &Operand) -> Result<i64, MachineErrorKind> {
+        match operand {
+            Operand::Register(reg) => match self.registers.get(reg) {
+                Some(value) => return Ok(*value),
+                None => return Err(MachineErrorKind::UnknownRegister(*reg)),
+            },
+            Operand::Value(value) => return Ok(*value),
+        }
     }
 
     /// Evaluates the arguments for the given instruction.
-    fn evaluate_instruction_args(&self, instruction: &SoundComputerInstruction) -> (Option<i64>, Option<i64>) {
+    fn evaluate_instruction_args(&self, instruction: &SoundComputerInstruction) -> Result<(Option<i64>, Option<i64>), MachineErrorKind> {
         match instruction {
-            SoundComputerInstruction::Snd{value_1} => {
-                return self.evaluate_instruction_args_one_value(&value_1);
-            },
-            SoundComputerInstruction::Set{value_1, value_2} => {
-                return self.evaluate_instruction_args_two_values(&value_1, &value_2);
-            },
-            SoundComputerInstruction::Add{value_1, value_2} => {
-                return self.evaluate_instruction_args_two_values(&value_1, &value_2);
-            },
-            SoundComputerInstruction::Mul{value_1, value_2} => {
-                return self.evaluate_instruction_args_two_values(&value_1, &value_2);
-            },
-            SoundComputerInstruction::Mod{value_1, value_2} => {
-                return self.evaluate_instruction_args_two_values(&value_1, &value_2);
-            },
+            SoundComputerInstruction::Snd{value_1} |
             SoundComputerInstruction::Rcv{value_1} => {
-                return self.evaluate_instruction_args_one_value(&value_1);
+                return Ok((Some(self.evaluate_operand(value_1)?), None));
             },
-            SoundComputerInstruction::Jgz{value_1, value_2} => {
-                return self.evaluate_instruction_args_two_values(&value_1, &value_2);
+            SoundComputerInstruction::Set{value_1, value_2} |
+            SoundComputerInstruction::Add{value_1, value_2} |
+            SoundComputerInstruction::Sub{value_1, value_2} |
+            SoundComputerInstruction::Mul{value_1, value_2} |
+            SoundComputerInstruction::Mod{value_1, value_2} |
+            SoundComputerInstruction::Jgz{value_1, value_2} |
+            SoundComputerInstruction::Jnz{value_1, value_2} => {
+                return Ok((Some(self.evaluate_operand(value_1)?), Some(self.evaluate_operand(value_2)?)));
             }
+            SoundComputerInstruction::Fused{kernel:_} => return Ok((None, None)),
         }
     }
 
-    /// Gets the number of times the SoundComputer has sent a value, for receipt by the other
-    /// SoundComputer in the duet.
-    /// 
-    /// Applicable when SoundComputer executed in double-mode.
+    /// Gets the number of times the SoundComputer has executed an snd instruction, sending a value
+    /// or playing a sound depending on its IoPolicy.
     pub fn snd_count(&self) -> usize {
         return self.snd_count;
     }
 
+    /// Gets the number of times an rcv instruction has received a value or recovered a frequency,
+    /// depending on the IoPolicy of the SoundComputer.
+    pub fn rcv_count(&self) -> usize {
+        return self.rcv_count;
+    }
+
+    /// Gets the number of times an instruction with the given opcode has been executed by the
+    /// SoundComputer. An rcv instruction that is waiting on input is not counted until it completes.
+    pub fn opcode_count(&self, opcode: Opcode) -> usize {
+        return *self.opcode_counts.get(&opcode).unwrap_or(&0);
+    }
+
+    /// Gets the total number of instructions executed by the SoundComputer.
+    pub fn instructions_executed(&self) -> usize {
+        return self.opcode_counts.values().sum();
+    }
+
+    /// Gets the execution count for every opcode, in opcode declaration order.
+    pub fn opcode_counts(&self) -> Vec<(Opcode, usize)> {
+        return Opcode::into_enum_iter().map(|x| (x, self.opcode_count(x))).collect();
+    }
+
+    /// Enables profiling mode, recording how many times each instruction is executed and how many
+    /// times each loop back-edge is taken from this point onwards. Any existing profile data is
+    /// discarded.
+    pub fn enable_profiling(&mut self) {
+        self.profiler = Some(Profiler::new(self.instructions.len()));
+    }
+
+    /// Disables profiling mode, discarding any profile data recorded.
+    pub fn disable_profiling(&mut self) {
+        self.profiler = None;
+    }
+
+    /// Generates a report from the data recorded since profiling was enabled. None is returned if
+    /// profiling is not enabled.
+    pub fn profile_report(&self) -> Option<ProfileReport> {
+        return self.profiler.as_ref().map(|x| x.report(&self.instructions));
+    }
+
+    /// Attaches the given observer to the SoundComputer, replacing any observer already attached.
+    /// The observer is notified before and after every instruction executed. Clones of the
+    /// SoundComputer are created without an observer attached.
+    pub fn set_observer(&mut self, observer: Arc<Mutex<dyn ExecutionObserver + Send>>) {
+        self.observer = Some(observer);
+    }
+
+    /// Detaches the current observer from the SoundComputer, if one is attached.
+    pub fn clear_observer(&mut self) {
+        self.observer = None;
+    }
+
     /// Indicates if the SoundComputer has attempted to receive an input value, but its input queue
     /// was empty on last attempt.
     pub fn is_awaiting_input(&self) -> bool {
@@ -147,88 +644,207 @@ impl SoundComputer {
         self.awaiting_input = false;
     }
 
-    /// Executes a single instruction in the SoundComputer, in either single-mode or double-mode.
-    fn execute_step(&mut self, double_mode: bool) {
+    /// Executes the next instruction in the SoundComputer.
+    /// Does nothing if the SoundComputer has already halted, unless it halted due to a fault, in
+    /// which case the fault is returned again.
+    pub fn step(&mut self) -> Result<(), MachineError> {
+        if let Some(fault) = self.fault {
+            return Err(fault);
+        }
+        if !self.halted {
+            self.execute_step()?;
+        }
+        return Ok(());
+    }
+
+    /// Executes a single instruction in the SoundComputer.
+    /// Returns false if the instruction could not complete because it is waiting on input.
+    ///
+    /// If the instruction faults, the SoundComputer is halted with the program counter left on the
+    /// faulting instruction and the fault is returned.
+    fn execute_step(&mut self) -> Result<bool, MachineError> {
         // Check if program counter is outside of instruction list, reaching a halting condition
         if self.prog_c >= self.instructions.len() {
             self.halted = true;
-            return;
+            return Ok(true);
         }
         // Evaluate the arguments of the current instruction
-        let instruction = &self.instructions[self.prog_c];
-        let args = self.evaluate_instruction_args(instruction);
-        // check the current instruction
+        let pc = self.prog_c;
+        let instruction = self.instructions[pc];
+        let args = match self.evaluate_instruction_args(&instruction) {
+            Ok(args) => args,
+            Err(kind) => return Err(self.raise_fault(kind)),
+        };
+        // Notify the observer and note the value of any register the instruction may write
+        let observer = self.observer.clone();
+        let mut prior_values = Vec::<(char, i64)>::new();
+        if let Some(observer) = &observer {
+            let operand_values = [args.0, args.1].iter().flatten().copied().collect::<Vec<i64>>();
+            observer.lock().unwrap().before_step(pc, &instruction, &operand_values);
+            for reg in written_registers(&instruction) {
+                prior_values.push((reg, *self.registers.get(&reg).unwrap_or(&0)));
+            }
+        }
+        let completed = match self.apply_instruction(instruction, args) {
+            Ok(completed) => completed,
+            Err(kind) => return Err(self.raise_fault(kind)),
+        };
+        if completed {
+            *self.opcode_counts.entry(instruction.opcode()).or_insert(0) += 1;
+            if let Some(profiler) = self.profiler.as_mut() {
+                profiler.record_instruction(pc);
+                // Only a taken jump can leave the program counter at or before the instruction
+                if !self.halted && self.prog_c <= pc {
+                    profiler.record_back_edge(pc, self.prog_c);
+                }
+            }
+        }
+        if let Some(observer) = &observer {
+            let mut deltas = Vec::<RegisterDelta>::new();
+            for (register, old_value) in prior_values {
+                let new_value = *self.registers.get(&register).unwrap_or(&0);
+                if new_value != old_value {
+                    deltas.push(RegisterDelta { register, old_value, new_value });
+                }
+            }
+            observer.lock().unwrap().after_step(pc, &instruction, self.prog_c, &deltas);
+        }
+        return Ok(completed);
+    }
+
+    /// Records a fault of the given kind against the instruction at the program counter and halts
+    /// the SoundComputer, returning the resulting error.
+    fn raise_fault(&mut self, kind: MachineErrorKind) -> MachineError {
+        let fault = MachineError::new(self.prog_c, self.instructions[self.prog_c], kind);
+        self.fault = Some(fault);
+        self.halted = true;
+        return fault;
+    }
+
+    /// Applies the effects of the given instruction, using its pre-evaluated arguments. Returns
+    /// false if the instruction could not complete because it is waiting on input.
+    fn apply_instruction(&mut self, instruction: SoundComputerInstruction, args: (Option<i64>, Option<i64>)) -> Result<bool, MachineErrorKind> {
         match instruction {
             SoundComputerInstruction::Snd{value_1:_} => {
                 self.prog_c += 1;
                 self.snd_count += 1;
-                if double_mode {
-                    self.output_queue.push_back(args.0.unwrap());
-                } else {
-                    self.single_mode_freqs_played.push_back(args.0.unwrap());
-                }
+                self.io.send(&mut self.output_queue, args.0.unwrap());
             },
             SoundComputerInstruction::Set{value_1, value_2:_} => {
+                let reg = destination_register(&value_1)?;
                 self.prog_c += 1;
-                self.registers.insert(value_1.chars().next().unwrap(), args.1.unwrap());
-            },
-            SoundComputerInstruction::Add{value_1, value_2:_} => {
-                self.prog_c += 1;
-                *self.registers.get_mut(&value_1.chars().next().unwrap()).unwrap() += args.1.unwrap();
-            },
-            SoundComputerInstruction::Mul{value_1, value_2:_} => {
-                self.prog_c += 1;
-                *self.registers.get_mut(&value_1.chars().next().unwrap()).unwrap() *= args.1.unwrap();
+                self.registers.insert(reg, args.1.unwrap());
             },
+            SoundComputerInstruction::Add{value_1, value_2:_} |
+            SoundComputerInstruction::Sub{value_1, value_2:_} |
+            SoundComputerInstruction::Mul{value_1, value_2:_} |
             SoundComputerInstruction::Mod{value_1, value_2:_} => {
+                let reg = destination_register(&value_1)?;
+                let result = self.overflow_policy.apply(instruction.opcode(), args.0.unwrap(), args.1.unwrap())?;
                 self.prog_c += 1;
-                let remainder = self.registers.get(&value_1.chars().next().unwrap()).unwrap() % args.1.unwrap();
-                self.registers.insert(value_1.chars().next().unwrap(), remainder);
+                self.registers.insert(reg, result);
             },
             SoundComputerInstruction::Rcv{value_1} => {
-                if double_mode { // double-mode execution option
-                    // Stop if awaiting input
-                    if self.awaiting_input || self.input_queue.is_empty() {
-                        self.awaiting_input = true;
-                        return;
-                    }
-                    self.prog_c += 1;
-                    let input_value = self.input_queue.pop_front().unwrap();
-                    let reg = value_1.chars().next().unwrap();
-                    self.registers.insert(reg, input_value);
-                } else { // single-mode execution option
-                    self.prog_c += 1;
-                    let check_val = args.0.unwrap();
-                    if check_val != 0 {
-                        let rcv_freq = self.single_mode_freqs_played.back();
-                        if rcv_freq.is_some() {
-                            self.recovered_freqs.push_back(*rcv_freq.unwrap());
-                            self.rcv_count += 1;
+                let reg = match self.io.receive_writes_register() {
+                    true => Some(destination_register(&value_1)?),
+                    false => None,
+                };
+                match self.receive(args.0.unwrap()) {
+                    Some(Some(value)) => {
+                        if let Some(reg) = reg {
+                            self.registers.insert(reg, value);
                         }
                     }
+                    Some(None) => (),
+                    None => return Ok(false),
                 }
             },
             SoundComputerInstruction::Jgz{value_1:_, value_2:_} => {
-                let check_val = args.0.unwrap();
                 // Check if we apply the jump instruction
-                if check_val > 0 {
-                    let jump_val = args.1.unwrap();
-                    // Check if negative jump would jump back off top of instructions
-                    if jump_val < 0 && jump_val.abs() as usize > self.prog_c {
-                        self.prog_c = 0;
-                        self.halted = true;
-                        return;
-                    }
-                    // Conduct the jump
-                    if jump_val < 0 {
-                        self.prog_c -= jump_val.abs() as usize;
-                    } else {
-                        self.prog_c += jump_val.abs() as usize;
-                    }
+                if args.0.unwrap() > 0 {
+                    self.conduct_jump(args.1.unwrap());
                 } else {
                     self.prog_c += 1;
                 }
+            },
+            SoundComputerInstruction::Jnz{value_1:_, value_2:_} => {
+                // Check if we apply the jump instruction
+                if args.0.unwrap() != 0 {
+                    self.conduct_jump(args.1.unwrap());
+                } else {
+                    self.prog_c += 1;
+                }
+            }
+            SoundComputerInstruction::Fused{kernel} => {
+                match kernel.evaluate(|x| self.evaluate_operand(x).ok()) {
+                    Some(writes) => {
+                        self.prog_c += kernel.loop_len();
+                        for (reg, value) in writes {
+                            self.registers.insert(reg, value);
+                        }
+                    }
+                    None => {
+                        // Guards not satisfied, so execute the original loop head instead
+                        let fallback = kernel.fallback();
+                        let args = self.evaluate_instruction_args(&fallback)?;
+                        return self.apply_instruction(fallback, args);
+                    }
+                }
+            }
+        }
+        return Ok(true);
+    }
+
+    /// Handles an rcv instruction with the given operand value using the IoPolicy, moving the
+    /// program counter on if the instruction completes. Returns None if the instruction is waiting on
+    /// input, otherwise the value to store in the register named by the rcv operand, if any.
+    fn receive(&mut self, value: i64) -> Option<Option<i64>> {
+        let stored = match self.io.receive(&mut self.input_queue, value) {
+            Receive::Block => {
+                self.awaiting_input = true;
+                return None;
+            }
+            Receive::Store(value) => {
+                self.rcv_count += 1;
+                Some(value)
+            }
+            Receive::Recovered => {
+                self.rcv_count += 1;
+                None
+            }
+            Receive::Ignored => None,
+        };
+        self.awaiting_input = false;
+        self.prog_c += 1;
+        return Some(stored);
+    }
+
+    /// Moves the program counter to the destination of a compiled jump instruction.
+    fn conduct_compiled_jump(&mut self, dest: JumpDest, registers: &[i64; REGISTER_COUNT]) {
+        match dest {
+            JumpDest::Instruction(target) => self.prog_c = target,
+            JumpDest::HaltAtTop => {
+                self.prog_c = 0;
+                self.halted = true;
             }
+            JumpDest::Offset(offset) => self.conduct_jump(offset.read(registers)),
+        }
+    }
+
+    /// Moves the program counter by the given jump offset. The SoundComputer is halted if the jump
+    /// would move the program counter back off the top of the instructions.
+    fn conduct_jump(&mut self, jump_val: i64) {
+        // Check if negative jump would jump back off top of instructions
+        if jump_val < 0 && jump_val.unsigned_abs() as usize > self.prog_c {
+            self.prog_c = 0;
+            self.halted = true;
+            return;
+        }
+        // Conduct the jump
+        if jump_val < 0 {
+            self.prog_c -= jump_val.unsigned_abs() as usize;
+        } else {
+            self.prog_c += jump_val as usize;
         }
     }
 
@@ -240,97 +856,406 @@ impl SoundComputer {
         self.halted = true;
     }
 
-    pub fn execute_single_mode(&mut self) {
+    /// Executes instructions until the SoundComputer halts, blocks on an rcv instruction, reaches the
+    /// event given or has executed the budgeted number of instructions. Values sent to the output
+    /// queue are left there for the caller to pop.
+    ///
+    /// A fault raised by an instruction, or a previous fault that halted the SoundComputer, is
+    /// returned as an error.
+    pub fn run_until(&mut self, until: RunUntil, budget: Option<usize>) -> Result<StopReason, MachineError> {
+        if let Some(program) = self.compiled_program() {
+            if let Some(fault) = self.fault {
+                return Err(fault);
+            }
+            return self.run_compiled(&program, until, budget);
+        }
+        let mut steps: usize = 0;
         loop {
-            // Check if we have reached the break condition for single mode execution
-            if self.rcv_count >= 1 {
-                return;
+            if let Some(fault) = self.fault {
+                return Err(fault);
+            }
+            if self.halted {
+                return Ok(StopReason::Halted);
             }
-            // Stop execution immediately if the SoundComputer has already halted.
-            if self.halted { 
-                return;
+            if budget.is_some_and(|x| steps >= x) {
+                return Ok(StopReason::BudgetExhausted);
             }
-            // Check if there is an output value
-            if !self.output_queue.is_empty() {
-                let output_val = self.output_queue.pop_front().unwrap();
-                self.input_queue.push_back(output_val);
+            let snd_count = self.snd_count;
+            let rcv_count = self.rcv_count;
+            if !self.execute_step()? {
+                return Ok(StopReason::AwaitingInput);
+            }
+            steps += 1;
+            // Check if the event being run until has occurred
+            match until {
+                RunUntil::Output if self.snd_count > snd_count => {
+                    return Ok(StopReason::Output);
+                }
+                RunUntil::Recover if self.rcv_count > rcv_count => {
+                    return Ok(StopReason::Recovered);
+                }
+                _ => (),
             }
-            self.execute_step(false);
         }
     }
 
-    pub fn execute_double_mode(&mut self) -> Option<i64> {
-        // Check if the SoundComputer has already halted
-        if self.halted {
+    /// Executes instructions until the SoundComputer halts, blocks on input, reaches the event given
+    /// or has executed the configured maximum number of steps.
+    pub fn run(&mut self, until: RunUntil) -> Result<StopReason, MachineError> {
+        return self.run_until(until, self.max_steps);
+    }
+
+    /// Returns the compiled program if the compiled backend can be used for the next run.
+    fn compiled_program(&self) -> Option<Arc<CompiledProgram>> {
+        if self.backend != Backend::Compiled || self.observer.is_some() || self.profiler.is_some() {
             return None;
         }
-        // Execute one step
-        self.execute_step(true);
-        // Check if there is an output value queued
-        if self.output_queue.is_empty() {
+        let program = self.compiled.clone()?;
+        // Registers removed by restoring a snapshot are only reported as faults by the interpreter
+        if program.registers().iter().any(|x| !self.registers.contains_key(x)) {
             return None;
-        } else {
-            return self.output_queue.pop_front();
         }
+        return Some(program);
     }
 
-    /// Generates the initial state for the registers referenced in the given instructions.
-    fn gen_initial_reg_state(instructions: &Vec<SoundComputerInstruction>) -> HashMap<char, i64> {
-        let mut reg_state = HashMap::<char, i64>::new();
-        for instruction in instructions {
-            match instruction {
-                SoundComputerInstruction::Snd{value_1} => {
-                    if !value_1.parse::<i64>().is_ok() {
-                        reg_state.insert(value_1.chars().next().unwrap(), 0);
-                    }
-                },
-                SoundComputerInstruction::Set{value_1, value_2} => {
-                    if !value_1.parse::<i64>().is_ok() {
-                        reg_state.insert(value_1.chars().next().unwrap(), 0);
-                    }
-                    if !value_2.parse::<i64>().is_ok() {
-                        reg_state.insert(value_2.chars().next().unwrap(), 0);
-                    }
-                },
-                SoundComputerInstruction::Add{value_1, value_2} => {
-                    if !value_1.parse::<i64>().is_ok() {
-                        reg_state.insert(value_1.chars().next().unwrap(), 0);
-                    }
-                    if !value_2.parse::<i64>().is_ok() {
-                        reg_state.insert(value_2.chars().next().unwrap(), 0);
-                    }
-                },
-                SoundComputerInstruction::Mul{value_1, value_2} => {
-                    if !value_1.parse::<i64>().is_ok() {
-                        reg_state.insert(value_1.chars().next().unwrap(), 0);
-                    }
-                    if !value_2.parse::<i64>().is_ok() {
-                        reg_state.insert(value_2.chars().next().unwrap(), 0);
-                    }
-                },
-                SoundComputerInstruction::Mod{value_1, value_2} => {
-                    if !value_1.parse::<i64>().is_ok() {
-                        reg_state.insert(value_1.chars().next().unwrap(), 0);
-                    }
-                    if !value_2.parse::<i64>().is_ok() {
-                        reg_state.insert(value_2.chars().next().unwrap(), 0);
+    /// Equivalent of run_until using the compiled program. Register values are loaded into a dense
+    /// array for the run, and written back along with the opcode counts when the run stops.
+    fn run_compiled(&mut self, program: &CompiledProgram, until: RunUntil, budget: Option<usize>) -> Result<StopReason, MachineError> {
+        let mut registers = [0; REGISTER_COUNT];
+        for reg in program.registers() {
+            registers[register_index(*reg).unwrap()] = self.registers[reg];
+        }
+        let mut counts = vec![0; program.ops().len()];
+        let result = self.run_compiled_loop(program, &mut registers, &mut counts, until, budget);
+        for reg in program.registers() {
+            self.registers.insert(*reg, registers[register_index(*reg).unwrap()]);
+        }
+        for (index, count) in counts.into_iter().enumerate().filter(|(_, x)| *x > 0) {
+            *self.opcode_counts.entry(program.opcode(index)).or_insert(0) += count;
+        }
+        return result.map_err(|kind| self.raise_fault(kind));
+    }
+
+    /// Executes compiled instructions until a stop condition of run_until is reached.
+    fn run_compiled_loop(&mut self, program: &CompiledProgram, registers: &mut [i64; REGISTER_COUNT], counts: &mut [usize], until: RunUntil, budget: Option<usize>) -> Result<StopReason, MachineErrorKind> {
+        let ops = program.ops();
+        let mut steps: usize = 0;
+        loop {
+            if self.halted {
+                return Ok(StopReason::Halted);
+            }
+            if budget.is_some_and(|x| steps >= x) {
+                return Ok(StopReason::BudgetExhausted);
+            }
+            let pc = self.prog_c;
+            if pc >= ops.len() {
+                self.halted = true;
+                return Ok(StopReason::Halted);
+            }
+            let snd_count = self.snd_count;
+            let rcv_count = self.rcv_count;
+            if !self.apply_compiled(ops[pc], registers)? {
+                return Ok(StopReason::AwaitingInput);
+            }
+            counts[pc] += 1;
+            steps += 1;
+            // Check if the event being run until has occurred
+            match until {
+                RunUntil::Output if self.snd_count > snd_count => {
+                    return Ok(StopReason::Output);
+                }
+                RunUntil::Recover if self.rcv_count > rcv_count => {
+                    return Ok(StopReason::Recovered);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Applies the effects of the given compiled instruction. Returns false if the instruction
+    /// could not complete because it is waiting on input.
+    fn apply_compiled(&mut self, op: CompiledOp, registers: &mut [i64; REGISTER_COUNT]) -> Result<bool, MachineErrorKind> {
+        match op {
+            CompiledOp::Snd(source) => {
+                self.prog_c += 1;
+                self.snd_count += 1;
+                self.io.send(&mut self.output_queue, source.read(registers));
+            }
+            CompiledOp::Set(dest, source) => {
+                self.prog_c += 1;
+                registers[dest] = source.read(registers);
+            }
+            CompiledOp::Arithmetic(opcode, dest, source) => {
+                registers[dest] = self.overflow_policy.apply(opcode, registers[dest], source.read(registers))?;
+                self.prog_c += 1;
+            }
+            CompiledOp::Rcv(slot) => {
+                let dest = match (self.io.receive_writes_register(), slot) {
+                    (true, Slot::Register(dest)) => Some(dest),
+                    (true, Slot::Value(value)) => return Err(MachineErrorKind::ImmediateDestination(value)),
+                    (false, _) => None,
+                };
+                match self.receive(slot.read(registers)) {
+                    Some(Some(value)) => {
+                        if let Some(dest) = dest {
+                            registers[dest] = value;
+                        }
                     }
-                },
-                SoundComputerInstruction::Rcv{value_1} => {
-                    if !value_1.parse::<i64>().is_ok() {
-                        reg_state.insert(value_1.chars().next().unwrap(), 0);
+                    Some(None) => (),
+                    None => return Ok(false),
+                }
+            }
+            CompiledOp::Jgz(test, dest) => {
+                if test.read(registers) > 0 {
+                    self.conduct_compiled_jump(dest, registers);
+                } else {
+                    self.prog_c += 1;
+                }
+            }
+            CompiledOp::Jnz(test, dest) => {
+                if test.read(registers) != 0 {
+                    self.conduct_compiled_jump(dest, registers);
+                } else {
+                    self.prog_c += 1;
+                }
+            }
+            CompiledOp::Fused(kernel) => {
+                let read = |operand: &Operand| match operand {
+                    Operand::Register(reg) => Some(registers[register_index(*reg)?]),
+                    Operand::Value(value) => Some(*value),
+                };
+                match kernel.evaluate(read) {
+                    Some(writes) => {
+                        self.prog_c += kernel.loop_len();
+                        for (reg, value) in writes {
+                            registers[register_index(reg).unwrap()] = value;
+                        }
                     }
-                },
-                SoundComputerInstruction::Jgz{value_1, value_2} => {
-                    if !value_1.parse::<i64>().is_ok() {
-                        reg_state.insert(value_1.chars().next().unwrap(), 0);
+                    None => {
+                        // Guards not satisfied, so execute the original loop head instead
+                        let fallback = compile_instruction(&kernel.fallback(), self.prog_c).unwrap();
+                        return self.apply_compiled(fallback, registers);
                     }
-                    if !value_2.parse::<i64>().is_ok() {
-                        reg_state.insert(value_2.chars().next().unwrap(), 0);
+                }
+            }
+            CompiledOp::Fault(kind) => return Err(kind),
+        }
+        return Ok(true);
+    }
+
+    /// Generates the initial state for the registers referenced in the given instructions.
+    fn gen_initial_reg_state(instructions: &[SoundComputerInstruction]) -> HashMap<char, i64> {
+        let mut reg_state = HashMap::<char, i64>::new();
+        for instruction in instructions {
+            let operands = match instruction {
+                SoundComputerInstruction::Snd{value_1} |
+                SoundComputerInstruction::Rcv{value_1} => vec![value_1],
+                SoundComputerInstruction::Set{value_1, value_2} |
+                SoundComputerInstruction::Add{value_1, value_2} |
+                SoundComputerInstruction::Sub{value_1, value_2} |
+                SoundComputerInstruction::Mul{value_1, value_2} |
+                SoundComputerInstruction::Mod{value_1, value_2} |
+                SoundComputerInstruction::Jgz{value_1, value_2} |
+                SoundComputerInstruction::Jnz{value_1, value_2} => vec![value_1, value_2],
+                SoundComputerInstruction::Fused{kernel} => {
+                    for reg in kernel.registers() {
+                        reg_state.insert(reg, 0);
                     }
+                    vec![]
                 }
             };
+            for operand in operands {
+                if let Operand::Register(reg) = operand {
+                    reg_state.insert(*reg, 0);
+                }
+            }
         }
         return reg_state;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::parse_program;
+    use super::super::TraceRecorder;
+
+    #[test]
+    fn test_snapshot_restore_fork() {
+        let input = std::fs::read_to_string("./input/2017/test/test_day18_01.txt").unwrap();
+        let program = parse_program(&input).unwrap();
+        let mut sound_comp = SoundComputer::with_io(&program, SoundIo::new());
+        for _ in 0..4 {
+            sound_comp.step().unwrap();
+        }
+        let checkpoint = sound_comp.snapshot();
+        assert_eq!(Some(&4), checkpoint.registers().get(&'a'));
+        // Fork the machine and change a register, leaving the original unaffected
+        let mut fork = sound_comp.clone();
+        fork.update_register('a', 1);
+        fork.step().unwrap();
+        assert_ne!(checkpoint, fork.snapshot());
+        assert_eq!(checkpoint, sound_comp.snapshot());
+        // Running then restoring returns the machine to the checkpointed state
+        sound_comp.execute_single_mode().unwrap();
+        assert_eq!(Some(4), sound_comp.last_recovered_freq());
+        sound_comp.restore(&checkpoint);
+        assert_eq!(checkpoint, sound_comp.snapshot());
+        // Looping back to the same state only compares equal once the counters are cleared
+        let mut sound_comp = SoundComputer::new(&parse_program("set a 1\njgz a 0").unwrap());
+        sound_comp.step().unwrap();
+        let before = sound_comp.snapshot();
+        sound_comp.step().unwrap();
+        assert_ne!(before, sound_comp.snapshot());
+        assert_eq!(before.without_counters(), sound_comp.snapshot().without_counters());
+    }
+
+    #[test]
+    fn test_run_until_stop_reasons() {
+        let input = std::fs::read_to_string("./input/2017/test/test_day18_02.txt").unwrap();
+        let program = parse_program(&input).unwrap();
+        let mut sound_comp = SoundComputer::new(&program);
+        assert_eq!(StopReason::Output, sound_comp.run_until(RunUntil::Output, None).unwrap());
+        assert_eq!(StopReason::BudgetExhausted, sound_comp.run_until(RunUntil::Block, Some(1)).unwrap());
+        assert_eq!(StopReason::AwaitingInput, sound_comp.run_until(RunUntil::Block, None).unwrap());
+        assert_eq!(vec![1, 2, 0], sound_comp.output_queue().iter().copied().collect::<Vec<i64>>());
+        for value in 0..4 {
+            sound_comp.push_input(value);
+        }
+        assert_eq!(StopReason::Halted, sound_comp.run_until(RunUntil::Block, None).unwrap());
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        let program = parse_program("set a 9223372036854775807\nadd a 1\nmod b 0").unwrap();
+        let mut sound_comp = SoundComputer::new(&program);
+        sound_comp.set_overflow_policy(OverflowPolicy::Checked);
+        let err = sound_comp.run_until(RunUntil::Block, None).unwrap_err();
+        assert_eq!((1, MachineErrorKind::ArithmeticOverflow), (err.pc(), err.kind()));
+        assert_eq!(Some(i64::MAX), sound_comp.register('a'));
+        // Faulted SoundComputer keeps reporting the same fault
+        assert_eq!(Err(err), sound_comp.step());
+        let mut sound_comp = SoundComputer::new(&program);
+        sound_comp.set_overflow_policy(OverflowPolicy::Saturating);
+        assert_eq!(StopReason::Halted, sound_comp.run_until(RunUntil::Block, None).unwrap());
+        assert_eq!((Some(i64::MAX), Some(0)), (sound_comp.register('a'), sound_comp.register('b')));
+        let mut sound_comp = SoundComputer::new(&program);
+        sound_comp.run_until(RunUntil::Block, None).unwrap();
+        assert_eq!(Some(i64::MIN), sound_comp.register('a'));
+        // Remainder of i64::MIN by -1 is zero, so is not an overflow even under the checked policy
+        let program = parse_program("set a -9223372036854775808\nmod a -1").unwrap();
+        for backend in [Backend::Interpreter, Backend::Compiled] {
+            let mut sound_comp = SoundComputer::new(&program);
+            sound_comp.set_overflow_policy(OverflowPolicy::Checked);
+            sound_comp.set_backend(backend);
+            assert_eq!(StopReason::Halted, sound_comp.run_until(RunUntil::Block, None).unwrap());
+            assert_eq!(Some(0), sound_comp.register('a'));
+        }
+    }
+
+    #[test]
+    fn test_immediate_destination_fault() {
+        let program = parse_program("set a 3\nrcv 5").unwrap();
+        let mut sound_comp = SoundComputer::new(&program);
+        sound_comp.push_input(1);
+        let err = sound_comp.run_until(RunUntil::Block, None).unwrap_err();
+        assert_eq!((1, MachineErrorKind::ImmediateDestination(5)), (err.pc(), err.kind()));
+        assert!(sound_comp.is_halted());
+        assert_eq!(1, sound_comp.input_queue().len());
+    }
+
+    #[test]
+    fn test_coprocessor_opcode_counts() {
+        // Counts down from 5 to 0, multiplying b by 2 on each pass through the loop
+        let program = parse_program("set a 5\nset b 1\nmul b 2\nsub a 1\njnz a -2").unwrap();
+        let mut sound_comp = SoundComputer::with_io(&program, SoundIo::new());
+        sound_comp.execute_single_mode().unwrap();
+        assert!(sound_comp.is_halted());
+        assert_eq!(5, sound_comp.opcode_count(Opcode::Mul));
+        assert_eq!(5, sound_comp.opcode_count(Opcode::Jnz));
+        assert_eq!(2, sound_comp.opcode_count(Opcode::Set));
+        assert_eq!(0, sound_comp.opcode_count(Opcode::Snd));
+    }
+
+    #[test]
+    fn test_profile_report_hot_loop() {
+        let program = parse_program("set a 3\nset b 4\nadd c 1\nsub b 1\njnz b -2\nsub a 1\njnz a -5").unwrap();
+        let mut sound_comp = SoundComputer::with_io(&program, SoundIo::new());
+        sound_comp.enable_profiling();
+        sound_comp.execute_single_mode().unwrap();
+        let report = sound_comp.profile_report().unwrap();
+        assert_eq!(&[1, 3, 12, 12, 12, 3, 3], report.instruction_counts());
+        // Inner loop back-edge taken 3 times per outer pass, outer back-edge taken twice
+        let loops = report.loops().iter().map(|x| (x.start(), x.end(), x.iterations())).collect::<Vec<_>>();
+        assert_eq!(vec![(2, 4, 9), (1, 6, 2)], loops);
+        assert!(report.opcode_counts().contains(&(Opcode::Jnz, 15)));
+    }
+
+    #[test]
+    fn test_trace_recorder_blocked_rcv() {
+        let program = parse_program("set a 7\nsnd a\nrcv b").unwrap();
+        let recorder = Arc::new(Mutex::new(TraceRecorder::new()));
+        let mut sound_comp = SoundComputer::new(&program);
+        sound_comp.set_observer(recorder.clone());
+        // Forked machine runs without reporting to the observer of the original
+        let mut fork = sound_comp.clone();
+        fork.execute_double_mode().unwrap();
+        assert!(recorder.lock().unwrap().entries().is_empty());
+        // Machines with an observer attached can still be moved across threads
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&sound_comp);
+        for _ in 0..4 {
+            sound_comp.execute_double_mode().unwrap();
+        }
+        let entries = recorder.lock().unwrap().entries().to_vec();
+        assert_eq!(4, entries.len());
+        assert_eq!(vec![RegisterDelta { register: 'a', old_value: 0, new_value: 7 }], entries[0].deltas);
+        assert_eq!(vec![7], entries[1].operand_values);
+        // Blocked rcv leaves program counter in place with no register changes
+        assert_eq!((2, 2), (entries[3].pc, entries[3].next_pc));
+        assert!(entries[3].deltas.is_empty());
+    }
+
+    #[test]
+    fn test_trace_recorder_limit() {
+        let program = parse_program("set a 1\nset b 2\nset c 3\nset d 4").unwrap();
+        let recorder = Arc::new(Mutex::new(TraceRecorder::with_limit(2)));
+        let mut sound_comp = SoundComputer::new(&program);
+        sound_comp.set_observer(recorder.clone());
+        sound_comp.run_until(RunUntil::Block, None).unwrap();
+        // Only the most recent entries are kept, oldest first
+        let pcs = recorder.lock().unwrap().entries().iter().map(|x| x.pc).collect::<Vec<usize>>();
+        assert_eq!(vec![2, 3], pcs);
+    }
+
+    #[test]
+    fn test_compiled_backend_matches_interpreter() {
+        fn check_backends<P: IoPolicy>(program: &[SoundComputerInstruction], io: P) {
+            let mut interpreted = SoundComputer::with_io(program, io.clone());
+            let mut compiled = SoundComputer::with_io(program, io);
+            compiled.set_backend(Backend::Compiled);
+            for sound_comp in [&mut interpreted, &mut compiled] {
+                sound_comp.update_register('p', 1);
+                for input in 0..20 {
+                    sound_comp.push_input(input);
+                }
+                sound_comp.run_until(RunUntil::Block, Some(500)).unwrap();
+                sound_comp.run_until(RunUntil::Recover, None).unwrap();
+            }
+            assert_eq!(interpreted.snapshot(), compiled.snapshot());
+        }
+        let input = std::fs::read_to_string("./input/2017/day18.txt").unwrap();
+        let program = parse_program(&input).unwrap();
+        check_backends(&program, SoundIo::new());
+        check_backends(&program, QueueIo);
+        // Faults leave the compiled machine in the same state as the interpreter
+        let program = parse_program("set a 3\nmul a a\nrcv 5").unwrap();
+        let mut compiled = SoundComputer::new(&program);
+        compiled.set_backend(Backend::Compiled);
+        compiled.push_input(1);
+        let err = compiled.run_until(RunUntil::Block, None).unwrap_err();
+        assert_eq!((2, MachineErrorKind::ImmediateDestination(5)), (err.pc(), err.kind()));
+        assert_eq!(Some(9), compiled.register('a'));
+        assert_eq!(1, compiled.opcode_count(Opcode::Mul));
+    }
+}
@@ -0,0 +1,485 @@
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+
+use super::soundcomp::{Operand, SoundComputerInstruction};
+use super::soundcomp_disasm::{resolve_jump_target, JumpTarget};
+use super::soundcomp_io::IoPolicy;
+
+/// Destination of a control-flow edge leaving an instruction or basic block.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, PartialOrd, Ord)]
+pub enum FlowTarget {
+    /// Edge to the instruction (or basic block) with the given index.
+    Node(usize),
+    /// Edge leaving the program, halting the SoundComputer.
+    Exit,
+    /// Edge to a location that depends on a register value at runtime.
+    Dynamic,
+}
+
+/// Whether the condition of a jump instruction can be determined without running the program.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum JumpCondition {
+    Always,
+    Never,
+    Conditional,
+}
+
+/// Classification of the offset of a jump instruction.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum JumpKind {
+    /// Offset is an immediate value, so the jump target is known statically.
+    Constant(JumpTarget),
+    /// Offset is read from the given register, so the jump target is only known at runtime.
+    RegisterDependent(char),
+}
+
+/// Details of a single jump instruction found in the program.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct JumpInfo {
+    pub index: usize,
+    pub condition: JumpCondition,
+    pub kind: JumpKind,
+}
+
+/// Maximal run of instructions with a single entry point and a single exit point.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct BasicBlock {
+    /// Index of the first instruction in the block.
+    pub start: usize,
+    /// Index of the last instruction in the block (inclusive).
+    pub end: usize,
+    /// Blocks (identified by block index) that control can flow to after the block.
+    pub successors: Vec<FlowTarget>,
+    pub reachable: bool,
+}
+
+/// Report produced by statically analysing a SoundComputer program.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ProgramAnalysis {
+    pub blocks: Vec<BasicBlock>,
+    pub jumps: Vec<JumpInfo>,
+    /// Indices of instructions that can never be executed.
+    pub unreachable: Vec<usize>,
+    /// Registers that may be read on some path before the program writes to them, relying on their
+    /// initial value.
+    pub read_before_write: BTreeSet<char>,
+    /// Indices of reachable rcv instructions that can never be satisfied.
+    pub unsatisfiable_rcvs: Vec<usize>,
+}
+
+impl ProgramAnalysis {
+    /// Returns the jumps with an immediate offset.
+    pub fn constant_jumps(&self) -> Vec<&JumpInfo> {
+        return self.jumps.iter().filter(|x| matches!(x.kind, JumpKind::Constant(_))).collect();
+    }
+
+    /// Returns the jumps with an offset read from a register.
+    pub fn register_dependent_jumps(&self) -> Vec<&JumpInfo> {
+        return self.jumps.iter().filter(|x| matches!(x.kind, JumpKind::RegisterDependent(_))).collect();
+    }
+
+    /// Renders the control-flow graph of the analysed program in Graphviz DOT format. Unreachable
+    /// blocks are drawn dashed.
+    pub fn to_dot(&self, instructions: &[SoundComputerInstruction]) -> String {
+        let mut output = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        output.push_str("    exit [shape=doublecircle, label=\"exit\"];\n");
+        if self.blocks.iter().any(|x| x.successors.contains(&FlowTarget::Dynamic)) {
+            output.push_str("    dynamic [shape=diamond, label=\"?\"];\n");
+        }
+        for (id, block) in self.blocks.iter().enumerate() {
+            let mut label = format!("[{}..{}]\\l", block.start, block.end);
+            for instruction in &instructions[block.start..=block.end] {
+                label.push_str(&format!("{}\\l", instruction));
+            }
+            let style = if block.reachable { "" } else { ", style=dashed" };
+            output.push_str(&format!("    b{} [label=\"{}\"{}];\n", id, label, style));
+        }
+        for (id, block) in self.blocks.iter().enumerate() {
+            for successor in &block.successors {
+                let dest = match successor {
+                    FlowTarget::Node(dest) => format!("b{}", dest),
+                    FlowTarget::Exit => String::from("exit"),
+                    FlowTarget::Dynamic => String::from("dynamic"),
+                };
+                output.push_str(&format!("    b{} -> {};\n", id, dest));
+            }
+        }
+        output.push_str("}\n");
+        return output;
+    }
+}
+
+/// Returns the register read by the given operand, if any.
+fn operand_register(operand: &Operand) -> Option<char> {
+    match operand {
+        Operand::Register(reg) => return Some(*reg),
+        Operand::Value(_) => return None,
+    }
+}
+
+/// Returns the registers read and the register written by the instruction. The rcv instruction
+/// writes its register if the IoPolicy receives values into registers, and otherwise reads it.
+fn register_usage(instruction: &SoundComputerInstruction, writes_register: bool) -> (Vec<char>, Option<char>) {
+    match instruction {
+        SoundComputerInstruction::Snd { value_1 } => {
+            return (operand_register(value_1).into_iter().collect(), None);
+        }
+        SoundComputerInstruction::Rcv { value_1 } => {
+            if writes_register {
+                return (vec![], operand_register(value_1));
+            }
+            return (operand_register(value_1).into_iter().collect(), None);
+        }
+        SoundComputerInstruction::Set { value_1, value_2 } => {
+            return (operand_register(value_2).into_iter().collect(), operand_register(value_1));
+        }
+        SoundComputerInstruction::Add { value_1, value_2 } |
+        SoundComputerInstruction::Sub { value_1, value_2 } |
+        SoundComputerInstruction::Mul { value_1, value_2 } |
+        SoundComputerInstruction::Mod { value_1, value_2 } => {
+            let reads = operand_register(value_1).into_iter().chain(operand_register(value_2)).collect();
+            return (reads, operand_register(value_1));
+        }
+        SoundComputerInstruction::Jgz { value_1, value_2 } |
+        SoundComputerInstruction::Jnz { value_1, value_2 } => {
+            return (operand_register(value_1).into_iter().chain(operand_register(value_2)).collect(), None);
+        }
+        // Fused loop reads every register of the loop it replaced, and always writes the register
+        // written by its original head instruction
+        SoundComputerInstruction::Fused { kernel } => {
+            let (_, write) = register_usage(&kernel.fallback(), writes_register);
+            return (kernel.registers(), write);
+        }
+    }
+}
+
+/// Classifies the jump instruction at the given index, returning None if it is not a jump.
+fn classify_jump(instructions: &[SoundComputerInstruction], index: usize) -> Option<JumpInfo> {
+    let (condition, offset) = match instructions[index] {
+        SoundComputerInstruction::Jgz { value_1: Operand::Value(x), value_2 } => {
+            (if x > 0 { JumpCondition::Always } else { JumpCondition::Never }, value_2)
+        }
+        SoundComputerInstruction::Jnz { value_1: Operand::Value(x), value_2 } => {
+            (if x != 0 { JumpCondition::Always } else { JumpCondition::Never }, value_2)
+        }
+        SoundComputerInstruction::Jgz { value_1: _, value_2 } |
+        SoundComputerInstruction::Jnz { value_1: _, value_2 } => (JumpCondition::Conditional, value_2),
+        _ => return None,
+    };
+    let kind = match offset {
+        Operand::Register(reg) => JumpKind::RegisterDependent(reg),
+        Operand::Value(_) => {
+            let target = resolve_jump_target(&instructions[index], index, instructions.len());
+            JumpKind::Constant(target.unwrap_or(JumpTarget::Halt))
+        }
+    };
+    return Some(JumpInfo { index, condition, kind });
+}
+
+/// Determines the control-flow edges leaving the instruction at the given index.
+fn instruction_successors(instructions: &[SoundComputerInstruction], index: usize) -> Vec<FlowTarget> {
+    let fall_through = {
+        if index + 1 < instructions.len() {
+            FlowTarget::Node(index + 1)
+        } else {
+            FlowTarget::Exit
+        }
+    };
+    if let SoundComputerInstruction::Fused { kernel } = instructions[index] {
+        // Fused loop either completes the whole loop or falls back to its original head instruction
+        let exit = index + kernel.loop_len();
+        let completed = if exit < instructions.len() { FlowTarget::Node(exit) } else { FlowTarget::Exit };
+        return vec![completed, fall_through];
+    }
+    let jump = match classify_jump(instructions, index) {
+        Some(jump) => jump,
+        None => return vec![fall_through],
+    };
+    let taken = match jump.kind {
+        JumpKind::Constant(JumpTarget::Instruction(target)) => FlowTarget::Node(target),
+        JumpKind::Constant(JumpTarget::Halt) => FlowTarget::Exit,
+        JumpKind::RegisterDependent(_) => FlowTarget::Dynamic,
+    };
+    match jump.condition {
+        JumpCondition::Always => return vec![taken],
+        JumpCondition::Never => return vec![fall_through],
+        JumpCondition::Conditional => {
+            if taken == fall_through {
+                return vec![taken];
+            }
+            return vec![taken, fall_through];
+        }
+    }
+}
+
+/// Determines which instructions can be reached from the start of the program. A register-dependent
+/// jump is conservatively assumed to be able to reach any instruction.
+fn find_reachable(successors: &[Vec<FlowTarget>]) -> Vec<bool> {
+    let mut reachable = vec![false; successors.len()];
+    if successors.is_empty() {
+        return reachable;
+    }
+    let mut queue = VecDeque::from(vec![0]);
+    reachable[0] = true;
+    while let Some(index) = queue.pop_front() {
+        for successor in &successors[index] {
+            let targets = match successor {
+                FlowTarget::Node(target) => vec![*target],
+                FlowTarget::Dynamic => (0..successors.len()).collect(),
+                FlowTarget::Exit => vec![],
+            };
+            for target in targets {
+                if !reachable[target] {
+                    reachable[target] = true;
+                    queue.push_back(target);
+                }
+            }
+        }
+    }
+    return reachable;
+}
+
+/// Finds the registers that may be read before being written, using a forward dataflow analysis of
+/// the registers definitely written on every path reaching each instruction.
+fn find_read_before_write(
+    instructions: &[SoundComputerInstruction],
+    successors: &[Vec<FlowTarget>],
+    reachable: &[bool],
+    writes_register: bool,
+) -> BTreeSet<char> {
+    let usage = instructions.iter().map(|x| register_usage(x, writes_register)).collect::<Vec<_>>();
+    // None represents the set of all registers (no path has reached the instruction yet)
+    let mut written_in: Vec<Option<BTreeSet<char>>> = vec![None; instructions.len()];
+    let mut queue = VecDeque::<usize>::new();
+    if !instructions.is_empty() {
+        written_in[0] = Some(BTreeSet::new());
+        queue.push_back(0);
+    }
+    while let Some(index) = queue.pop_front() {
+        let mut written_out = written_in[index].clone().unwrap();
+        if let Some(reg) = usage[index].1 {
+            written_out.insert(reg);
+        }
+        let mut targets = Vec::<usize>::new();
+        for successor in &successors[index] {
+            match successor {
+                FlowTarget::Node(target) => targets.push(*target),
+                FlowTarget::Dynamic => targets.extend(0..instructions.len()),
+                FlowTarget::Exit => (),
+            }
+        }
+        for target in targets {
+            let merged = match &written_in[target] {
+                Some(existing) => existing.intersection(&written_out).copied().collect(),
+                None => written_out.clone(),
+            };
+            if written_in[target].as_ref() != Some(&merged) {
+                written_in[target] = Some(merged);
+                queue.push_back(target);
+            }
+        }
+    }
+    let mut read_before_write = BTreeSet::<char>::new();
+    for index in 0..instructions.len() {
+        if !reachable[index] {
+            continue;
+        }
+        // Register-dependent jumps have already merged their written set into every instruction
+        let written = written_in[index].clone().unwrap_or_default();
+        for reg in &usage[index].0 {
+            if !written.contains(reg) {
+                read_before_write.insert(*reg);
+            }
+        }
+    }
+    return read_before_write;
+}
+
+/// Finds the reachable rcv instructions that can never be satisfied. If the IoPolicy receives values
+/// into registers, as QueueIo does, every rcv is unsatisfiable when paired with a machine running the
+/// same program if no snd is reachable. Otherwise rcv is taken to recover the last value sent, as
+/// SoundIo does, which can never happen if its operand is zero or no snd can execute before it.
+fn find_unsatisfiable_rcvs(
+    instructions: &[SoundComputerInstruction],
+    successors: &[Vec<FlowTarget>],
+    reachable: &[bool],
+    writes_register: bool,
+) -> Vec<usize> {
+    let is_snd = |i: usize| matches!(instructions[i], SoundComputerInstruction::Snd { .. });
+    let is_rcv = |i: usize| matches!(instructions[i], SoundComputerInstruction::Rcv { .. });
+    let rcvs = (0..instructions.len()).filter(|i| reachable[*i] && is_rcv(*i)).collect::<Vec<usize>>();
+    if writes_register {
+        if (0..instructions.len()).any(|i| reachable[i] && is_snd(i)) {
+            return vec![];
+        }
+        return rcvs;
+    }
+    // Determine the instructions that can execute after a reachable snd
+    let after_snd = (0..instructions.len())
+        .map(|i| if reachable[i] && is_snd(i) { successors[i].clone() } else { vec![] })
+        .collect::<Vec<Vec<FlowTarget>>>();
+    let mut visited = vec![false; instructions.len()];
+    let mut queue = VecDeque::<usize>::new();
+    for targets in &after_snd {
+        for target in targets {
+            match target {
+                FlowTarget::Node(x) => queue.push_back(*x),
+                FlowTarget::Dynamic => queue.extend(0..instructions.len()),
+                FlowTarget::Exit => (),
+            }
+        }
+    }
+    while let Some(index) = queue.pop_front() {
+        if visited[index] {
+            continue;
+        }
+        visited[index] = true;
+        for target in &successors[index] {
+            match target {
+                FlowTarget::Node(x) => queue.push_back(*x),
+                FlowTarget::Dynamic => queue.extend(0..instructions.len()),
+                FlowTarget::Exit => (),
+            }
+        }
+    }
+    return rcvs
+        .into_iter()
+        .filter(|i| {
+            let zero_operand = instructions[*i] == SoundComputerInstruction::Rcv { value_1: Operand::Value(0) };
+            zero_operand || !visited[*i]
+        })
+        .collect();
+}
+
+/// Splits the program into basic blocks, linking each block to its successor blocks.
+fn build_blocks(successors: &[Vec<FlowTarget>], reachable: &[bool]) -> Vec<BasicBlock> {
+    let len = successors.len();
+    // Determine the instructions that start a new basic block
+    let mut leaders = BTreeSet::<usize>::new();
+    if len > 0 {
+        leaders.insert(0);
+    }
+    for (index, targets) in successors.iter().enumerate() {
+        let is_branch = targets.len() != 1 || targets[0] != FlowTarget::Node(index + 1);
+        if is_branch {
+            if index + 1 < len {
+                leaders.insert(index + 1);
+            }
+            for target in targets {
+                if let FlowTarget::Node(x) = target {
+                    leaders.insert(*x);
+                }
+            }
+        }
+    }
+    let starts = leaders.into_iter().collect::<Vec<usize>>();
+    let mut block_of = vec![0; len];
+    for (id, start) in starts.iter().enumerate() {
+        let end = if id + 1 < starts.len() { starts[id + 1] } else { len };
+        for slot in block_of.iter_mut().take(end).skip(*start) {
+            *slot = id;
+        }
+    }
+    let mut blocks = Vec::<BasicBlock>::new();
+    for (id, start) in starts.iter().enumerate() {
+        let end = if id + 1 < starts.len() { starts[id + 1] - 1 } else { len - 1 };
+        let successors = successors[end]
+            .iter()
+            .map(|x| match x {
+                FlowTarget::Node(target) => FlowTarget::Node(block_of[*target]),
+                other => *other,
+            })
+            .collect();
+        blocks.push(BasicBlock { start: *start, end, successors, reachable: reachable[*start] });
+    }
+    return blocks;
+}
+
+/// Statically analyses the given program, building its control-flow graph and reporting
+/// unreachable instructions, registers read before being written and unsatisfiable rcv
+/// instructions. The rcv instruction is interpreted according to the given IoPolicy, based on
+/// whether it receives values into the register named by the rcv operand.
+pub fn analyse_program<P: IoPolicy>(instructions: &[SoundComputerInstruction], io: &P) -> ProgramAnalysis {
+    let writes_register = io.receive_writes_register();
+    let successors = (0..instructions.len())
+        .map(|i| instruction_successors(instructions, i))
+        .collect::<Vec<Vec<FlowTarget>>>();
+    let reachable = find_reachable(&successors);
+    let jumps = (0..instructions.len()).filter_map(|i| classify_jump(instructions, i)).collect();
+    return ProgramAnalysis {
+        blocks: build_blocks(&successors, &reachable),
+        jumps,
+        unreachable: (0..instructions.len()).filter(|i| !reachable[*i]).collect(),
+        read_before_write: find_read_before_write(instructions, &successors, &reachable, writes_register),
+        unsatisfiable_rcvs: find_unsatisfiable_rcvs(instructions, &successors, &reachable, writes_register),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{optimise_program, parse_program, QueueIo, SoundIo};
+
+    #[test]
+    fn test_analyse_day_18_program() {
+        let input = std::fs::read_to_string("./input/2017/day18.txt").unwrap();
+        let program = parse_program(&input).unwrap();
+        let analysis = analyse_program(&program, &QueueIo);
+        assert!(analysis.unreachable.is_empty());
+        assert!(analysis.unsatisfiable_rcvs.is_empty());
+        assert!(analysis.register_dependent_jumps().len() == 1);
+        // Register-dependent jump "jgz p p" may enter any instruction, but only after 'a' and 'i' are
+        // written, so only the registers read before that point are reported
+        let read_before_write = analysis.read_before_write.iter().copied().collect::<Vec<char>>();
+        assert_eq!(vec!['b', 'f', 'p'], read_before_write);
+        let dot = analysis.to_dot(&program);
+        assert!(dot.starts_with("digraph cfg {"));
+        assert!(dot.contains("dynamic [shape=diamond"));
+    }
+
+    #[test]
+    fn test_analyse_unreachable_and_rcvs() {
+        let program = parse_program("set a 1\njgz 1 2\nsnd a\nrcv a\nadd b c").unwrap();
+        let analysis = analyse_program(&program, &QueueIo);
+        assert_eq!(vec![2], analysis.unreachable);
+        assert_eq!(vec![3], analysis.unsatisfiable_rcvs);
+        assert_eq!(vec!['b', 'c'], analysis.read_before_write.iter().copied().collect::<Vec<char>>());
+        let starts = analysis.blocks.iter().map(|x| (x.start, x.end, x.reachable)).collect::<Vec<_>>();
+        assert_eq!(vec![(0, 1, true), (2, 2, false), (3, 4, true)], starts);
+        assert_eq!(vec![FlowTarget::Node(2)], analysis.blocks[0].successors);
+        assert_eq!(vec![FlowTarget::Exit], analysis.blocks[2].successors);
+    }
+
+    #[test]
+    fn test_analyse_io_policy_rcv_semantics() {
+        let program = parse_program("rcv a\nsnd a\nrcv a\nrcv 0").unwrap();
+        // Receiving into registers, rcv writes its register and a reachable snd can satisfy it
+        let analysis = analyse_program(&program, &QueueIo);
+        assert!(analysis.read_before_write.is_empty());
+        assert!(analysis.unsatisfiable_rcvs.is_empty());
+        // Recovering sounds, rcv reads its register and needs a prior snd and a non-zero operand
+        let analysis = analyse_program(&program, &SoundIo::new());
+        assert_eq!(vec!['a'], analysis.read_before_write.iter().copied().collect::<Vec<char>>());
+        assert_eq!(vec![0, 3], analysis.unsatisfiable_rcvs);
+    }
+
+    #[test]
+    fn test_analyse_extreme_jump_offsets() {
+        let program = parse_program("set a 1\njgz a 9223372036854775807\njnz 1 -9223372036854775808").unwrap();
+        let analysis = analyse_program(&program, &QueueIo);
+        let kinds = analysis.jumps.iter().map(|x| x.kind).collect::<Vec<JumpKind>>();
+        assert_eq!(vec![JumpKind::Constant(JumpTarget::Halt); 2], kinds);
+        assert_eq!(vec![FlowTarget::Exit, FlowTarget::Node(1)], analysis.blocks[0].successors);
+        assert_eq!(vec![FlowTarget::Exit], analysis.blocks[1].successors);
+    }
+
+    #[test]
+    fn test_analyse_fused_loop_reads_counter() {
+        let program = optimise_program(&parse_program("add b c\nsub a 1\njnz a -2").unwrap());
+        assert!(matches!(program[0], SoundComputerInstruction::Fused { .. }));
+        // Loop counter is read by the fused loop even though its fallback instruction does not read it
+        let analysis = analyse_program(&program, &QueueIo);
+        let read_before_write = analysis.read_before_write.iter().copied().collect::<Vec<char>>();
+        assert_eq!(vec!['a', 'b', 'c'], read_before_write);
+    }
+}
@@ -0,0 +1,116 @@
+use super::soundcomp::{Backend, OverflowPolicy, SoundComputer, SoundComputerInstruction};
+use super::soundcomp_io::{IoPolicy, QueueIo};
+
+/// Builder for a SoundComputer, configuring its initial register values, pre-seeded input queue,
+/// IoPolicy and limits in one place. Registers not given a value are zero-initialised.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SoundComputerBuilder<P = QueueIo> {
+    registers: Vec<(char, i64)>,
+    input: Vec<i64>,
+    io: P,
+    max_steps: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    backend: Backend,
+}
+
+impl Default for SoundComputerBuilder {
+    fn default() -> Self {
+        Self {
+            registers: vec![],
+            input: vec![],
+            io: QueueIo,
+            max_steps: None,
+            overflow_policy: OverflowPolicy::Wrapping,
+            backend: Backend::Interpreter,
+        }
+    }
+}
+
+impl SoundComputerBuilder {
+    /// Creates a new builder for a SoundComputer using the send and receive semantics of QueueIo.
+    pub fn new() -> Self {
+        return Self::default();
+    }
+}
+
+impl<P: IoPolicy> SoundComputerBuilder<P> {
+
+    /// Sets the initial value of the given register. The register is added to the SoundComputer
+    /// even if the program does not reference it.
+    pub fn register(mut self, reg: char, value: i64) -> Self {
+        self.registers.retain(|(x, _)| *x != reg);
+        self.registers.push((reg, value));
+        return self;
+    }
+
+    /// Appends the given values to the input queue the SoundComputer starts with.
+    pub fn input(mut self, values: impl IntoIterator<Item = i64>) -> Self {
+        self.input.extend(values);
+        return self;
+    }
+
+    /// Sets the IoPolicy determining the semantics of the snd and rcv instructions.
+    pub fn io<Q: IoPolicy>(self, io: Q) -> SoundComputerBuilder<Q> {
+        return SoundComputerBuilder {
+            registers: self.registers,
+            input: self.input,
+            io,
+            max_steps: self.max_steps,
+            overflow_policy: self.overflow_policy,
+            backend: self.backend,
+        };
+    }
+
+    /// Sets the maximum number of instructions executed by each call to SoundComputer::run.
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = Some(max_steps);
+        return self;
+    }
+
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        return self;
+    }
+
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        return self;
+    }
+
+    /// Builds a SoundComputer loaded with the given program, in the configured initial state.
+    pub fn build(&self, instructions: &[SoundComputerInstruction]) -> SoundComputer<P> {
+        let mut computer = SoundComputer::with_io(instructions, self.io.clone());
+        for (reg, value) in &self.registers {
+            computer.update_register(*reg, *value);
+        }
+        for value in &self.input {
+            computer.push_input(*value);
+        }
+        computer.set_max_steps(self.max_steps);
+        computer.set_overflow_policy(self.overflow_policy);
+        computer.set_backend(self.backend);
+        return computer;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{parse_program, RunUntil, StopReason};
+
+    #[test]
+    fn test_builder_initial_state() {
+        let program = parse_program("rcv a\nadd a p\nsnd a\njgz 1 -3").unwrap();
+        let mut computer = SoundComputer::builder()
+            .register('p', 10)
+            .register('z', -1)
+            .input([1, 2])
+            .max_steps(5)
+            .build(&program);
+        assert_eq!(vec![('a', 0), ('p', 10), ('z', -1)], computer.registers().collect::<Vec<(char, i64)>>());
+        assert_eq!(StopReason::BudgetExhausted, computer.run(RunUntil::Block).unwrap());
+        assert_eq!(Some(11), computer.pop_output());
+        assert_eq!(StopReason::AwaitingInput, computer.run(RunUntil::Block).unwrap());
+        assert_eq!(vec![('a', 12), ('p', 10), ('z', -1)], computer.registers().collect::<Vec<(char, i64)>>());
+    }
+}
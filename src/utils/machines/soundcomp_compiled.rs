@@ -0,0 +1,182 @@
+use super::soundcomp::{Opcode, Operand, SoundComputerInstruction};
+use super::soundcomp_error::MachineErrorKind;
+use super::soundcomp_optimise::FusedKernel;
+
+/// Number of registers available to a compiled program, named 'a' to 'z'.
+pub(crate) const REGISTER_COUNT: usize = 26;
+
+/// Returns the index of the given register in the dense register array, if it has one.
+pub(crate) fn register_index(reg: char) -> Option<usize> {
+    if reg.is_ascii_lowercase() {
+        return Some(reg as usize - 'a' as usize);
+    }
+    return None;
+}
+
+/// Operand of a compiled instruction, with any register name resolved to its register index.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Slot {
+    Register(usize),
+    Value(i64),
+}
+
+impl Slot {
+    fn compile(operand: &Operand) -> Option<Slot> {
+        match operand {
+            Operand::Register(reg) => return register_index(*reg).map(Slot::Register),
+            Operand::Value(value) => return Some(Slot::Value(*value)),
+        }
+    }
+
+    /// Returns the value of the operand, using the given register values.
+    #[inline]
+    pub(crate) fn read(&self, registers: &[i64; REGISTER_COUNT]) -> i64 {
+        match self {
+            Slot::Register(index) => return registers[*index],
+            Slot::Value(value) => return *value,
+        }
+    }
+}
+
+/// Destination of a compiled jump instruction.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum JumpDest {
+    /// Immediate offset, pre-resolved to the index of the target instruction.
+    Instruction(usize),
+    /// Immediate offset that moves back off the top of the program, halting the SoundComputer.
+    HaltAtTop,
+    /// Offset read from a register at runtime.
+    Offset(Slot),
+}
+
+/// Instruction of a compiled program. Destination operands are resolved to register indices, with
+/// an immediate destination compiled to a Fault raised when the instruction is executed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum CompiledOp {
+    Snd(Slot),
+    Set(usize, Slot),
+    Arithmetic(Opcode, usize, Slot),
+    Rcv(Slot),
+    Jgz(Slot, JumpDest),
+    Jnz(Slot, JumpDest),
+    Fused(FusedKernel),
+    Fault(MachineErrorKind),
+}
+
+/// SoundComputer program compiled to use a dense register array and pre-resolved jump targets.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct CompiledProgram {
+    ops: Vec<CompiledOp>,
+    opcodes: Vec<Opcode>,
+    registers: Vec<char>,
+}
+
+impl CompiledProgram {
+    /// Compiles the given program. None is returned if the program uses a register outside of 'a'
+    /// to 'z', which only the reference interpreter supports.
+    pub(crate) fn compile(instructions: &[SoundComputerInstruction]) -> Option<Self> {
+        let mut ops = Vec::<CompiledOp>::new();
+        let mut registers = Vec::<char>::new();
+        for (index, instruction) in instructions.iter().enumerate() {
+            ops.push(compile_instruction(instruction, index)?);
+            let used = match instruction {
+                SoundComputerInstruction::Snd { value_1 } |
+                SoundComputerInstruction::Rcv { value_1 } => vec![*value_1],
+                SoundComputerInstruction::Set { value_1, value_2 } |
+                SoundComputerInstruction::Add { value_1, value_2 } |
+                SoundComputerInstruction::Sub { value_1, value_2 } |
+                SoundComputerInstruction::Mul { value_1, value_2 } |
+                SoundComputerInstruction::Mod { value_1, value_2 } |
+                SoundComputerInstruction::Jgz { value_1, value_2 } |
+                SoundComputerInstruction::Jnz { value_1, value_2 } => vec![*value_1, *value_2],
+                SoundComputerInstruction::Fused { kernel } => {
+                    kernel.registers().into_iter().map(Operand::Register).collect()
+                }
+            };
+            for operand in used {
+                if let Operand::Register(reg) = operand {
+                    register_index(reg)?;
+                    if !registers.contains(&reg) {
+                        registers.push(reg);
+                    }
+                }
+            }
+        }
+        registers.sort_unstable();
+        return Some(Self {
+            ops,
+            opcodes: instructions.iter().map(|x| x.opcode()).collect(),
+            registers,
+        });
+    }
+
+    pub(crate) fn ops(&self) -> &[CompiledOp] {
+        return &self.ops;
+    }
+
+    /// Returns the opcode of the source instruction at the given index.
+    pub(crate) fn opcode(&self, index: usize) -> Opcode {
+        return self.opcodes[index];
+    }
+
+    /// Returns the names of the registers used by the program, in ascending order.
+    pub(crate) fn registers(&self) -> &[char] {
+        return &self.registers;
+    }
+}
+
+/// Compiles the destination operand of an instruction that writes a register.
+fn compile_destination(operand: &Operand) -> Option<Result<usize, MachineErrorKind>> {
+    match operand {
+        Operand::Register(reg) => return register_index(*reg).map(Ok),
+        Operand::Value(value) => return Some(Err(MachineErrorKind::ImmediateDestination(*value))),
+    }
+}
+
+/// Compiles the offset operand of the jump instruction at the given index.
+fn compile_jump(operand: &Operand, index: usize) -> Option<JumpDest> {
+    match operand {
+        Operand::Value(offset) => {
+            let target = (index as i64).saturating_add(*offset);
+            if target < 0 {
+                return Some(JumpDest::HaltAtTop);
+            }
+            return Some(JumpDest::Instruction(target as usize));
+        }
+        Operand::Register(_) => return Slot::compile(operand).map(JumpDest::Offset),
+    }
+}
+
+/// Compiles a single instruction found at the given index of the program. None is returned if the
+/// instruction uses a register outside of 'a' to 'z'.
+pub(crate) fn compile_instruction(instruction: &SoundComputerInstruction, index: usize) -> Option<CompiledOp> {
+    let op = match instruction {
+        SoundComputerInstruction::Snd { value_1 } => CompiledOp::Snd(Slot::compile(value_1)?),
+        SoundComputerInstruction::Rcv { value_1 } => CompiledOp::Rcv(Slot::compile(value_1)?),
+        SoundComputerInstruction::Set { value_1, value_2 } => {
+            let source = Slot::compile(value_2)?;
+            match compile_destination(value_1)? {
+                Ok(dest) => CompiledOp::Set(dest, source),
+                Err(kind) => CompiledOp::Fault(kind),
+            }
+        }
+        SoundComputerInstruction::Add { value_1, value_2 } |
+        SoundComputerInstruction::Sub { value_1, value_2 } |
+        SoundComputerInstruction::Mul { value_1, value_2 } |
+        SoundComputerInstruction::Mod { value_1, value_2 } => {
+            let source = Slot::compile(value_2)?;
+            match compile_destination(value_1)? {
+                Ok(dest) => CompiledOp::Arithmetic(instruction.opcode(), dest, source),
+                Err(kind) => CompiledOp::Fault(kind),
+            }
+        }
+        SoundComputerInstruction::Jgz { value_1, value_2 } => {
+            CompiledOp::Jgz(Slot::compile(value_1)?, compile_jump(value_2, index)?)
+        }
+        SoundComputerInstruction::Jnz { value_1, value_2 } => {
+            CompiledOp::Jnz(Slot::compile(value_1)?, compile_jump(value_2, index)?)
+        }
+        SoundComputerInstruction::Fused { kernel } => CompiledOp::Fused(*kernel),
+    };
+    return Some(op);
+}
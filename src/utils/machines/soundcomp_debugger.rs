@@ -0,0 +1,228 @@
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+
+use super::soundcomp::{SoundComputer, SoundComputerInstruction};
+use super::soundcomp_io::{IoPolicy, QueueIo};
+use super::soundcomp_error::MachineError;
+
+/// Condition under which a watchpoint on a register triggers.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum WatchCondition {
+    /// Triggers whenever the register value changes.
+    Changed,
+    /// Triggers when the register value moves from at or below the threshold to above it.
+    CrossesAbove(i64),
+    /// Triggers when the register value moves from at or above the threshold to below it.
+    CrossesBelow(i64),
+}
+
+impl WatchCondition {
+    /// Checks if the condition is triggered by the register changing between the given values.
+    fn is_triggered(&self, old_value: i64, new_value: i64) -> bool {
+        match self {
+            WatchCondition::Changed => return old_value != new_value,
+            WatchCondition::CrossesAbove(threshold) => return old_value <= *threshold && new_value > *threshold,
+            WatchCondition::CrossesBelow(threshold) => return old_value >= *threshold && new_value < *threshold,
+        }
+    }
+}
+
+/// Reason the Debugger stopped executing the SoundComputer program.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum DebugStop {
+    /// Program counter reached a breakpoint. The instruction at the breakpoint is yet to execute.
+    Breakpoint(usize),
+    /// A watchpoint was triggered by the instruction at the given program counter.
+    Watchpoint { pc: usize, register: char, old_value: i64, new_value: i64 },
+    /// The SoundComputer is blocked on an rcv instruction with an empty input queue.
+    AwaitingInput,
+    Halted,
+    /// The requested number of steps were executed without any other stop occurring.
+    StepsCompleted,
+}
+
+/// Interactive debugger for SoundComputer programs, supporting breakpoints on instruction indices,
+/// watchpoints on registers and inspection and modification of machine state while paused.
+pub struct Debugger<P = QueueIo> {
+    computer: SoundComputer<P>,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<(char, WatchCondition)>,
+}
+
+impl<P: IoPolicy> Debugger<P> {
+    /// Creates a new Debugger controlling the given SoundComputer.
+    pub fn new(computer: SoundComputer<P>) -> Self {
+        Self {
+            computer,
+            breakpoints: BTreeSet::new(),
+            watchpoints: vec![],
+        }
+    }
+
+    /// Sets a breakpoint on the instruction at the given index.
+    pub fn add_breakpoint(&mut self, index: usize) {
+        self.breakpoints.insert(index);
+    }
+
+    /// Removes the breakpoint on the instruction at the given index, returning true if one was set.
+    pub fn remove_breakpoint(&mut self, index: usize) -> bool {
+        return self.breakpoints.remove(&index);
+    }
+
+    /// Returns the indices of all breakpoints set, in ascending order.
+    pub fn breakpoints(&self) -> Vec<usize> {
+        return self.breakpoints.iter().copied().collect();
+    }
+
+    /// Adds a watchpoint on the given register.
+    pub fn add_watchpoint(&mut self, reg: char, condition: WatchCondition) {
+        self.watchpoints.push((reg, condition));
+    }
+
+    /// Removes all watchpoints set on the given register.
+    pub fn remove_watchpoints(&mut self, reg: char) {
+        self.watchpoints.retain(|(x, _)| *x != reg);
+    }
+
+    /// Executes a single instruction, ignoring any breakpoint on it. Returns the stop caused by the
+    /// step, if any, or the fault raised by the instruction.
+    pub fn step(&mut self) -> Result<Option<DebugStop>, MachineError> {
+        if self.computer.is_halted() {
+            return self.computer.step().map(|_| Some(DebugStop::Halted));
+        }
+        // Record watched register values before executing the instruction
+        let pc = self.computer.prog_c();
+        let before = self
+            .watchpoints
+            .iter()
+            .map(|(reg, _)| self.computer.register(*reg).unwrap_or(0))
+            .collect::<Vec<i64>>();
+        self.computer.step()?;
+        // Check for any triggered watchpoints
+        for (i, (reg, condition)) in self.watchpoints.iter().enumerate() {
+            let new_value = self.computer.register(*reg).unwrap_or(0);
+            if condition.is_triggered(before[i], new_value) {
+                return Ok(Some(DebugStop::Watchpoint { pc, register: *reg, old_value: before[i], new_value }));
+            }
+        }
+        if self.computer.is_halted() {
+            return Ok(Some(DebugStop::Halted));
+        }
+        if self.computer.is_awaiting_input() && self.computer.prog_c() == pc {
+            return Ok(Some(DebugStop::AwaitingInput));
+        }
+        return Ok(None);
+    }
+
+    /// Executes up to the given number of instructions, stopping early on a breakpoint, watchpoint,
+    /// halt, blocked rcv or fault. Any breakpoint on the current instruction is stepped over.
+    pub fn step_n(&mut self, steps: usize) -> Result<DebugStop, MachineError> {
+        for i in 0..steps {
+            if i > 0 && self.breakpoints.contains(&self.computer.prog_c()) {
+                return Ok(DebugStop::Breakpoint(self.computer.prog_c()));
+            }
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+        }
+        return Ok(DebugStop::StepsCompleted);
+    }
+
+    /// Executes until a breakpoint, watchpoint, halt, blocked rcv or fault is reached. Any breakpoint
+    /// on the current instruction is stepped over.
+    pub fn run(&mut self) -> Result<DebugStop, MachineError> {
+        if let Some(stop) = self.step()? {
+            return Ok(stop);
+        }
+        loop {
+            if self.breakpoints.contains(&self.computer.prog_c()) {
+                return Ok(DebugStop::Breakpoint(self.computer.prog_c()));
+            }
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+        }
+    }
+
+    /// Returns the current value of the program counter.
+    pub fn pc(&self) -> usize {
+        return self.computer.prog_c();
+    }
+
+    /// Returns the instruction that will be executed next, if the program counter is within the
+    /// program.
+    pub fn current_instruction(&self) -> Option<&SoundComputerInstruction> {
+        return self.computer.current_instruction();
+    }
+
+    /// Returns the current value of the given register.
+    pub fn register(&self, reg: char) -> Option<i64> {
+        return self.computer.register(reg);
+    }
+
+    /// Sets the given register to the given value.
+    pub fn set_register(&mut self, reg: char, value: i64) {
+        self.computer.update_register(reg, value);
+    }
+
+    /// Returns the values waiting in the input queue of the SoundComputer.
+    pub fn input_queue(&self) -> &VecDeque<i64> {
+        return self.computer.input_queue();
+    }
+
+    /// Returns the values waiting in the output queue of the SoundComputer.
+    pub fn output_queue(&self) -> &VecDeque<i64> {
+        return self.computer.output_queue();
+    }
+
+    /// Pushes the given value to the end of the input queue of the SoundComputer.
+    pub fn push_input(&mut self, input: i64) {
+        self.computer.push_input(input);
+    }
+
+    /// Pops the first value from the output queue of the SoundComputer.
+    pub fn pop_output(&mut self) -> Option<i64> {
+        return self.computer.pop_output();
+    }
+
+    /// Returns a reference to the SoundComputer being debugged.
+    pub fn computer(&self) -> &SoundComputer<P> {
+        return &self.computer;
+    }
+
+    /// Returns a mutable reference to the SoundComputer being debugged.
+    pub fn computer_mut(&mut self) -> &mut SoundComputer<P> {
+        return &mut self.computer;
+    }
+
+    /// Consumes the Debugger, returning the SoundComputer it was controlling.
+    pub fn into_inner(self) -> SoundComputer<P> {
+        return self.computer;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::parse_program;
+
+    #[test]
+    fn test_debugger_pause_at_rcv() {
+        let input = std::fs::read_to_string("./input/2017/test/test_day18_02.txt").unwrap();
+        let program = parse_program(&input).unwrap();
+        let mut debugger = Debugger::new(SoundComputer::new(&program));
+        debugger.add_breakpoint(3);
+        assert_eq!(DebugStop::Breakpoint(3), debugger.run().unwrap());
+        assert_eq!(vec![1, 2, 0], debugger.output_queue().iter().copied().collect::<Vec<i64>>());
+        // Feed one value through and watch register a receive it
+        debugger.add_watchpoint('a', WatchCondition::CrossesAbove(10));
+        debugger.push_input(42);
+        let stop = debugger.run().unwrap();
+        assert_eq!(DebugStop::Watchpoint { pc: 3, register: 'a', old_value: 0, new_value: 42 }, stop);
+        assert_eq!(DebugStop::AwaitingInput, debugger.run().unwrap());
+        assert_eq!(4, debugger.pc());
+        debugger.set_register('b', -1);
+        assert_eq!(Some(-1), debugger.register('b'));
+        assert_eq!(DebugStop::StepsCompleted, debugger.step_n(0).unwrap());
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+
+use super::soundcomp::{Operand, SoundComputerInstruction};
+
+/// Options controlling the annotations included by format_program. Annotations are emitted as
+/// ';' comments so the formatted output can always be parsed back into the same program, except
+/// that fused instructions are parsed back as the original loop head instructions they replaced.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct FormatOptions {
+    pub show_indices: bool,
+    pub show_jump_targets: bool,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Register(reg) => write!(f, "{}", reg),
+            Operand::Value(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl SoundComputerInstruction {
+    /// Returns the mnemonic used for the instruction in SoundComputer source text.
+    pub fn mnemonic(&self) -> &'static str {
+        return self.opcode().mnemonic();
+    }
+}
+
+impl fmt::Display for SoundComputerInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SoundComputerInstruction::Snd { value_1 } |
+            SoundComputerInstruction::Rcv { value_1 } => {
+                write!(f, "{} {}", self.mnemonic(), value_1)
+            }
+            SoundComputerInstruction::Set { value_1, value_2 } |
+            SoundComputerInstruction::Add { value_1, value_2 } |
+            SoundComputerInstruction::Sub { value_1, value_2 } |
+            SoundComputerInstruction::Mul { value_1, value_2 } |
+            SoundComputerInstruction::Mod { value_1, value_2 } |
+            SoundComputerInstruction::Jgz { value_1, value_2 } |
+            SoundComputerInstruction::Jnz { value_1, value_2 } => {
+                write!(f, "{} {} {}", self.mnemonic(), value_1, value_2)
+            }
+            SoundComputerInstruction::Fused { kernel } => write!(f, "{} {}", self.mnemonic(), kernel),
+        }
+    }
+}
+
+/// Destination of a jump instruction with an immediate offset.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, PartialOrd, Ord)]
+pub enum JumpTarget {
+    Instruction(usize),
+    Halt,
+}
+
+/// Resolves the target of the instruction at the given index, if it is a jump with an immediate
+/// offset. Jumps landing outside of the program resolve to JumpTarget::Halt.
+pub(crate) fn resolve_jump_target(instruction: &SoundComputerInstruction, index: usize, len: usize) -> Option<JumpTarget> {
+    match instruction {
+        SoundComputerInstruction::Jgz { value_1: _, value_2: Operand::Value(offset) } |
+        SoundComputerInstruction::Jnz { value_1: _, value_2: Operand::Value(offset) } => {
+            match (index as i64).checked_add(*offset) {
+                Some(target) if target >= 0 && target < len as i64 => {
+                    return Some(JumpTarget::Instruction(target as usize));
+                }
+                _ => return Some(JumpTarget::Halt),
+            }
+        }
+        _ => return None,
+    }
+}
+
+/// Formats the given program as canonical SoundComputer source text, one instruction per line.
+/// Instruction indices and resolved jump targets are appended as comments if enabled in the
+/// options, with jumps that leave the program shown as "-> halt". Fused instructions have no source
+/// text form, so are emitted as the original loop head instruction with the fused instruction
+/// given in a comment.
+pub fn format_program(instructions: &[SoundComputerInstruction], options: &FormatOptions) -> String {
+    let lines = instructions
+        .iter()
+        .map(|x| match x {
+            SoundComputerInstruction::Fused { kernel } => kernel.fallback().to_string(),
+            _ => x.to_string(),
+        })
+        .collect::<Vec<String>>();
+    let width = lines.iter().map(|x| x.len()).max().unwrap_or(0);
+    let mut output = String::new();
+    for (index, line) in lines.iter().enumerate() {
+        // Build up the annotations for the current instruction
+        let mut annotations = Vec::<String>::new();
+        if options.show_indices {
+            annotations.push(format!("[{}]", index));
+        }
+        if options.show_jump_targets {
+            match resolve_jump_target(&instructions[index], index, instructions.len()) {
+                Some(JumpTarget::Instruction(target)) => annotations.push(format!("-> {}", target)),
+                Some(JumpTarget::Halt) => annotations.push(String::from("-> halt")),
+                None => (),
+            }
+        }
+        if let SoundComputerInstruction::Fused { .. } = instructions[index] {
+            annotations.push(instructions[index].to_string());
+        }
+        if annotations.is_empty() {
+            output.push_str(line);
+        } else {
+            output.push_str(&format!("{:<width$}  ; {}", line, annotations.join(" "), width = width));
+        }
+        output.push('\n');
+    }
+    return output;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{optimise_program, parse_program};
+
+    #[test]
+    fn test_format_program_round_trip() {
+        let input = std::fs::read_to_string("./input/2017/day18.txt").unwrap();
+        let program = parse_program(&input).unwrap();
+        let canonical = format_program(&program, &FormatOptions::default());
+        assert_eq!(input.trim(), canonical.trim());
+        let options = FormatOptions { show_indices: true, show_jump_targets: true };
+        let annotated = format_program(&program, &options);
+        assert_eq!(program, parse_program(&annotated).unwrap());
+    }
+
+    #[test]
+    fn test_format_program_fused_instructions() {
+        let program = parse_program("set c 5\nadd a b\nsub c 1\njgz c -2").unwrap();
+        let optimised = optimise_program(&program);
+        let formatted = format_program(&optimised, &FormatOptions::default());
+        assert_eq!("add a b   ; fused muladd a b c", formatted.lines().nth(1).unwrap());
+        let parsed = parse_program(&formatted).unwrap();
+        assert_eq!(program, parsed);
+        assert_eq!(optimised, optimise_program(&parsed));
+    }
+
+    #[test]
+    fn test_format_program_jump_targets() {
+        let program = parse_program("set a 1\njgz a -1\njgz a b\njgz a 5").unwrap();
+        let options = FormatOptions { show_indices: false, show_jump_targets: true };
+        let expected = "set a 1\njgz a -1  ; -> 0\njgz a b\njgz a 5   ; -> halt\n";
+        assert_eq!(expected, format_program(&program, &options));
+        // Offsets overflowing the target index also leave the program
+        let program = parse_program("set a 1\njgz a 9223372036854775807\njnz a -9223372036854775808").unwrap();
+        let expected = format!(
+            "set a 1\njgz a {}   ; -> halt\njnz a {}  ; -> halt\n",
+            i64::MAX,
+            i64::MIN
+        );
+        assert_eq!(expected, format_program(&program, &options));
+    }
+}
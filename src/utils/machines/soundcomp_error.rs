@@ -0,0 +1,62 @@
+use std::error::Error;
+use std::fmt;
+
+use super::soundcomp::SoundComputerInstruction;
+
+/// Categorises the runtime faults that can occur while a SoundComputer executes an instruction.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum MachineErrorKind {
+    /// Result of an arithmetic instruction does not fit in a register under the checked policy.
+    ArithmeticOverflow,
+    /// Divisor of a mod instruction was zero under the checked policy.
+    DivisionByZero,
+    /// Instruction that writes a register was given an immediate value as its destination.
+    ImmediateDestination(i64),
+    /// Operand referenced a register that is not present in the SoundComputer.
+    UnknownRegister(char),
+}
+
+/// Runtime fault raised by a SoundComputer, recording the program counter and instruction at which
+/// the fault occurred.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct MachineError {
+    pc: usize,
+    instruction: SoundComputerInstruction,
+    kind: MachineErrorKind,
+}
+
+impl MachineError {
+    pub fn new(pc: usize, instruction: SoundComputerInstruction, kind: MachineErrorKind) -> Self {
+        Self { pc, instruction, kind }
+    }
+
+    /// Returns the program counter of the instruction that caused the fault.
+    pub fn pc(&self) -> usize {
+        return self.pc;
+    }
+
+    /// Returns the instruction that caused the fault.
+    pub fn instruction(&self) -> &SoundComputerInstruction {
+        return &self.instruction;
+    }
+
+    pub fn kind(&self) -> MachineErrorKind {
+        return self.kind;
+    }
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            MachineErrorKind::ArithmeticOverflow => write!(f, "arithmetic overflow")?,
+            MachineErrorKind::DivisionByZero => write!(f, "division by zero")?,
+            MachineErrorKind::ImmediateDestination(value) => {
+                write!(f, "immediate value {} used as destination register", value)?
+            }
+            MachineErrorKind::UnknownRegister(reg) => write!(f, "unknown register '{}'", reg)?,
+        }
+        write!(f, " at pc {} ({})", self.pc, self.instruction)
+    }
+}
+
+impl Error for MachineError {}
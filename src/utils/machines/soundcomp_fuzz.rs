@@ -0,0 +1,177 @@
+//! Random program generators and property tests for the SoundComputer.
+
+use proptest::prelude::*;
+
+use super::{format_program, optimise_program, parse_program};
+use super::{Backend, FormatOptions, Operand, QueueIo, RunUntil, SoundComputer, SoundComputerInstruction, SoundIo};
+
+/// Registers used by generated programs. Kept small so instructions frequently share registers.
+const REGISTERS: [char; 5] = ['a', 'b', 'c', 'd', 'e'];
+
+fn register() -> impl Strategy<Value = char> {
+    return prop::sample::select(&REGISTERS[..]);
+}
+
+/// Immediate values, mostly small but occasionally at the extremes to exercise overflow handling.
+fn value() -> impl Strategy<Value = i64> {
+    return prop_oneof![8 => -5i64..=5, 1 => any::<i64>()];
+}
+
+fn operand() -> impl Strategy<Value = Operand> {
+    return prop_oneof![register().prop_map(Operand::Register), value().prop_map(Operand::Value)];
+}
+
+fn destination() -> impl Strategy<Value = Operand> {
+    return register().prop_map(Operand::Register);
+}
+
+/// Jump offsets, mostly immediate and small enough to usually land within the program.
+fn jump_offset() -> impl Strategy<Value = Operand> {
+    return prop_oneof![4 => (-8i64..=8).prop_map(Operand::Value), 1 => register().prop_map(Operand::Register)];
+}
+
+/// Generates a single well-formed instruction, with every destination operand naming a register.
+fn instruction(jumps: bool) -> BoxedStrategy<SoundComputerInstruction> {
+    let straight = prop_oneof![
+        operand().prop_map(|value_1| SoundComputerInstruction::Snd { value_1 }),
+        destination().prop_map(|value_1| SoundComputerInstruction::Rcv { value_1 }),
+        (destination(), operand()).prop_map(|(value_1, value_2)| SoundComputerInstruction::Set { value_1, value_2 }),
+        (destination(), operand()).prop_map(|(value_1, value_2)| SoundComputerInstruction::Add { value_1, value_2 }),
+        (destination(), operand()).prop_map(|(value_1, value_2)| SoundComputerInstruction::Sub { value_1, value_2 }),
+        (destination(), operand()).prop_map(|(value_1, value_2)| SoundComputerInstruction::Mul { value_1, value_2 }),
+        (destination(), operand()).prop_map(|(value_1, value_2)| SoundComputerInstruction::Mod { value_1, value_2 }),
+    ];
+    if !jumps {
+        return straight.boxed();
+    }
+    return prop_oneof![
+        4 => straight,
+        1 => (operand(), jump_offset()).prop_map(|(value_1, value_2)| SoundComputerInstruction::Jgz { value_1, value_2 }),
+        1 => (operand(), jump_offset()).prop_map(|(value_1, value_2)| SoundComputerInstruction::Jnz { value_1, value_2 }),
+    ]
+    .boxed();
+}
+
+/// Generates a program of up to the given length, optionally including jump instructions.
+fn program(max_len: usize, jumps: bool) -> impl Strategy<Value = Vec<SoundComputerInstruction>> {
+    return prop::collection::vec(instruction(jumps), 1..=max_len);
+}
+
+/// Generates a counted multiply-accumulate loop recognised by the peephole optimiser, preceded by an
+/// instruction initialising its counter.
+fn mul_add_loop() -> impl Strategy<Value = Vec<SoundComputerInstruction>> {
+    let registers = prop::sample::subsequence(&REGISTERS[..], 3).prop_shuffle();
+    return (registers, -3i64..=20, operand(), any::<bool>(), any::<bool>()).prop_map(|(regs, count, addend, sub, jgz)| {
+        let (target, counter) = (Operand::Register(regs[0]), Operand::Register(regs[1]));
+        let addend = if addend == target || addend == counter { Operand::Register(regs[2]) } else { addend };
+        let decrement = match sub {
+            true => SoundComputerInstruction::Sub { value_1: counter, value_2: Operand::Value(1) },
+            false => SoundComputerInstruction::Add { value_1: counter, value_2: Operand::Value(-1) },
+        };
+        let jump = match jgz {
+            true => SoundComputerInstruction::Jgz { value_1: counter, value_2: Operand::Value(-2) },
+            false => SoundComputerInstruction::Jnz { value_1: counter, value_2: Operand::Value(-2) },
+        };
+        return vec![
+            SoundComputerInstruction::Set { value_1: counter, value_2: Operand::Value(count) },
+            SoundComputerInstruction::Add { value_1: target, value_2: addend },
+            decrement,
+            jump,
+        ];
+    });
+}
+
+/// Generates the divisor search loop from AoC 2017 Day 23, preceded by instructions initialising
+/// its registers.
+fn divisor_search_loop() -> impl Strategy<Value = Vec<SoundComputerInstruction>> {
+    return (1i64..=12, 0i64..=12, 1i64..=60).prop_map(|(divisor, factor, number)| {
+        return parse_program(&format!(
+            "set d {}\nset e {}\nset b {}\nset f 1\nset c d\nmul c e\nsub c b\njnz c 2\nset f 0\n\
+            sub e -1\nset c e\nsub c b\njnz c -8",
+            divisor, factor, number
+        ))
+        .unwrap();
+    });
+}
+
+/// Generates a program built from straight-line code and loops recognised by the peephole optimiser.
+fn optimisable_program() -> impl Strategy<Value = Vec<SoundComputerInstruction>> {
+    let segment = prop_oneof![
+        prop::collection::vec(instruction(false), 0..4),
+        mul_add_loop(),
+        divisor_search_loop(),
+    ];
+    return prop::collection::vec(segment, 1..5).prop_map(|x| x.concat());
+}
+
+proptest! {
+    #[test]
+    fn prop_snapshot_restore_is_identity(
+        program in program(20, true),
+        inputs in prop::collection::vec(value(), 0..5),
+        steps in 0usize..200,
+        more_steps in 1usize..200,
+    ) {
+        let mut sound_comp = SoundComputer::new(&program);
+        for input in inputs {
+            sound_comp.push_input(input);
+        }
+        sound_comp.run_until(RunUntil::Block, Some(steps)).unwrap();
+        let checkpoint = sound_comp.snapshot();
+        let mut fork = sound_comp.clone();
+        sound_comp.run_until(RunUntil::Block, Some(more_steps)).unwrap();
+        let after = sound_comp.snapshot();
+        sound_comp.restore(&checkpoint);
+        prop_assert_eq!(&checkpoint, &sound_comp.snapshot());
+        // Restored machine continues exactly as an untouched copy of the original
+        sound_comp.run_until(RunUntil::Block, Some(more_steps)).unwrap();
+        fork.run_until(RunUntil::Block, Some(more_steps)).unwrap();
+        prop_assert_eq!(&after, &sound_comp.snapshot());
+        prop_assert_eq!(after, fork.snapshot());
+    }
+
+    #[test]
+    fn prop_compiled_backend_matches_interpreter(program in program(20, true), budget in 0usize..500) {
+        let mut interpreted = SoundComputer::with_io(&program, SoundIo::new());
+        let mut compiled = SoundComputer::with_io(&program, SoundIo::new());
+        compiled.set_backend(Backend::Compiled);
+        let reason = interpreted.run_until(RunUntil::Block, Some(budget)).unwrap();
+        prop_assert_eq!(reason, compiled.run_until(RunUntil::Block, Some(budget)).unwrap());
+        prop_assert_eq!(interpreted.snapshot(), compiled.snapshot());
+    }
+
+    #[test]
+    fn prop_optimised_program_matches_reference(program in optimisable_program()) {
+        let mut reference = SoundComputer::new(&program);
+        let reason = reference.run_until(RunUntil::Block, Some(5_000)).unwrap();
+        // Only programs that stop within the budget can be compared
+        prop_assume!(reference.is_halted() || reference.is_awaiting_input());
+        let optimised_program = optimise_program(&program);
+        for backend in [Backend::Interpreter, Backend::Compiled] {
+            let mut optimised = SoundComputer::with_io(&optimised_program, QueueIo);
+            optimised.set_backend(backend);
+            prop_assert_eq!(reason, optimised.run_until(RunUntil::Block, None).unwrap());
+            prop_assert_eq!(reference.prog_c(), optimised.prog_c());
+            prop_assert_eq!(reference.registers().collect::<Vec<_>>(), optimised.registers().collect::<Vec<_>>());
+            prop_assert_eq!(reference.output_queue(), optimised.output_queue());
+        }
+    }
+
+    #[test]
+    fn prop_program_without_jumps_halts_within_len_steps(program in program(20, false)) {
+        let mut sound_comp = SoundComputer::with_io(&program, SoundIo::new());
+        sound_comp.run_until(RunUntil::Block, Some(program.len())).unwrap();
+        prop_assert!(sound_comp.current_instruction().is_none());
+        prop_assert_eq!(program.len(), sound_comp.instructions_executed());
+    }
+
+    #[test]
+    fn prop_parser_round_trips_disassembly(
+        program in program(20, true),
+        show_indices in any::<bool>(),
+        show_jump_targets in any::<bool>(),
+    ) {
+        let text = format_program(&program, &FormatOptions { show_indices, show_jump_targets });
+        prop_assert_eq!(program, parse_program(&text).unwrap());
+    }
+}
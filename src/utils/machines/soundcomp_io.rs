@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// Outcome of an rcv instruction, as determined by the IoPolicy of the SoundComputer.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum Receive {
+    /// Value received is written into the register named by the rcv operand.
+    Store(i64),
+    /// Value was received without writing any register, such as a recovered frequency.
+    Recovered,
+    /// Instruction completes without receiving a value.
+    Ignored,
+    /// Instruction cannot complete yet, leaving the SoundComputer waiting on input.
+    Block,
+}
+
+/// Determines the semantics of the snd and rcv instructions executed by a SoundComputer. The
+/// SoundComputer owns an input queue and an output queue, which the policy may use along with any
+/// state of its own. Policy state is included in SoundComputer snapshots.
+pub trait IoPolicy: Clone + PartialEq + Eq + Hash + Debug {
+    /// Handles an snd instruction with the given operand value.
+    fn send(&mut self, output: &mut VecDeque<i64>, value: i64);
+
+    /// Checks if a completed rcv instruction writes into the register named by its operand, in
+    /// which case an immediate operand is a fault.
+    fn receive_writes_register(&self) -> bool;
+
+    /// Handles an rcv instruction with the given operand value. Called again on each attempt to
+    /// execute an rcv instruction that previously blocked.
+    fn receive(&mut self, input: &mut VecDeque<i64>, value: i64) -> Receive;
+}
+
+/// Semantics from AoC 2017 Day 18 part 2 (double-mode). The snd instruction sends a value to the
+/// output queue, and the rcv instruction receives a value from the input queue into its register,
+/// blocking while the input queue is empty.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub struct QueueIo;
+
+impl IoPolicy for QueueIo {
+    fn send(&mut self, output: &mut VecDeque<i64>, value: i64) {
+        output.push_back(value);
+    }
+
+    fn receive_writes_register(&self) -> bool {
+        return true;
+    }
+
+    fn receive(&mut self, input: &mut VecDeque<i64>, _value: i64) -> Receive {
+        match input.pop_front() {
+            Some(value) => return Receive::Store(value),
+            None => return Receive::Block,
+        }
+    }
+}
+
+/// Semantics from AoC 2017 Day 18 part 1 (single-mode). The snd instruction plays a sound with a
+/// frequency equal to its operand, and the rcv instruction recovers the frequency of the last sound
+/// played if its operand is non-zero. The input and output queues are unused.
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub struct SoundIo {
+    played: VecDeque<i64>,
+    recovered: VecDeque<i64>,
+}
+
+impl SoundIo {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Returns the frequencies of the sounds played, oldest first.
+    pub fn played(&self) -> &VecDeque<i64> {
+        return &self.played;
+    }
+
+    /// Returns the frequencies recovered, oldest first.
+    pub fn recovered(&self) -> &VecDeque<i64> {
+        return &self.recovered;
+    }
+
+    /// Returns the most recently recovered frequency.
+    pub fn last_recovered(&self) -> Option<i64> {
+        return self.recovered.back().copied();
+    }
+}
+
+impl IoPolicy for SoundIo {
+    fn send(&mut self, _output: &mut VecDeque<i64>, value: i64) {
+        self.played.push_back(value);
+    }
+
+    fn receive_writes_register(&self) -> bool {
+        return false;
+    }
+
+    fn receive(&mut self, _input: &mut VecDeque<i64>, value: i64) -> Receive {
+        if value == 0 {
+            return Receive::Ignored;
+        }
+        match self.played.back() {
+            Some(freq) => {
+                self.recovered.push_back(*freq);
+                return Receive::Recovered;
+            }
+            None => return Receive::Ignored,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{parse_program, RunUntil, SoundComputer, StopReason};
+
+    /// Receives from the input queue, giving up on an rcv after it has blocked a number of times.
+    #[derive(Clone, PartialEq, Eq, Debug, Hash)]
+    struct TimeoutIo {
+        timeout: usize,
+        attempts: usize,
+    }
+
+    impl IoPolicy for TimeoutIo {
+        fn send(&mut self, output: &mut VecDeque<i64>, value: i64) {
+            output.push_back(value);
+        }
+
+        fn receive_writes_register(&self) -> bool {
+            return true;
+        }
+
+        fn receive(&mut self, input: &mut VecDeque<i64>, _value: i64) -> Receive {
+            if let Some(value) = input.pop_front() {
+                self.attempts = 0;
+                return Receive::Store(value);
+            }
+            self.attempts += 1;
+            if self.attempts > self.timeout {
+                self.attempts = 0;
+                return Receive::Ignored;
+            }
+            return Receive::Block;
+        }
+    }
+
+    #[test]
+    fn test_custom_io_policy_timeout() {
+        let program = parse_program("rcv a\nadd a 1\nsnd a").unwrap();
+        let mut sound_comp = SoundComputer::with_io(&program, TimeoutIo { timeout: 2, attempts: 0 });
+        assert_eq!(StopReason::AwaitingInput, sound_comp.run_until(RunUntil::Block, None).unwrap());
+        assert_eq!(StopReason::AwaitingInput, sound_comp.run_until(RunUntil::Block, None).unwrap());
+        assert_eq!(StopReason::Halted, sound_comp.run_until(RunUntil::Block, None).unwrap());
+        assert_eq!(Some(1), sound_comp.pop_output());
+        assert_eq!(0, sound_comp.rcv_count());
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use super::soundcomp::{RunUntil, SoundComputer, SoundComputerState, StopReason};
+use super::soundcomp_error::MachineError;
+use super::soundcomp_io::{IoPolicy, QueueIo};
+use super::soundcomp_replay::{TrafficLog, TrafficRecord};
+
+/// Maximum number of instructions a machine executes before the Network moves to the next machine.
+const TIME_SLICE: usize = 10_000;
+
+/// Determines which machines in a Network receive the values sent by each machine.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum Topology {
+    /// Machines are paired off (0 with 1, 2 with 3 and so on), each sending to its partner. Values
+    /// sent by an unpaired final machine are discarded.
+    Pairwise,
+    /// Each machine sends to the next machine, with the last machine sending to the first.
+    Ring,
+    /// Each machine sends to every other machine.
+    Broadcast,
+}
+
+impl Topology {
+    /// Returns the indices of the machines that receive values sent by the given source machine, in
+    /// a network of the given size.
+    pub fn destinations(&self, source: usize, size: usize) -> Vec<usize> {
+        match self {
+            Topology::Pairwise => {
+                let partner = source ^ 1;
+                if partner < size {
+                    return vec![partner];
+                }
+                return vec![];
+            }
+            Topology::Ring => {
+                if size < 2 {
+                    return vec![];
+                }
+                return vec![(source + 1) % size];
+            }
+            Topology::Broadcast => return (0..size).filter(|x| *x != source).collect(),
+        }
+    }
+}
+
+/// Final state reached by a Network when it stops running.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum NetworkOutcome {
+    /// Every machine in the network has halted.
+    AllHalted,
+    /// Every machine that has not halted is waiting on input, with no values left to deliver.
+    Deadlock,
+    /// The network returned to a state it had already been in, so would repeat forever. The machine
+    /// still running that has gone longest without sending or receiving is reported as stuck.
+    Livelock { machine: usize, pc: usize },
+    /// The step budget of the network was used up. The machine still running that has gone longest
+    /// without sending or receiving is reported as stuck.
+    BudgetExhausted { machine: usize, pc: usize },
+}
+
+/// Summary of a Network run, with send and receive counts indexed by machine.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct NetworkReport {
+    pub outcome: NetworkOutcome,
+    pub sends: Vec<usize>,
+    pub receives: Vec<usize>,
+}
+
+/// Runtime fault raised by one of the machines in a Network.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct NetworkError {
+    pub machine: usize,
+    pub error: MachineError,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "machine {}: {}", self.machine, self.error)
+    }
+}
+
+impl Error for NetworkError {}
+
+/// Hosts a number of SoundComputers, routing the values each machine sends to its output queue to
+/// the input queues of other machines according to the network topology.
+pub struct Network<P = QueueIo> {
+    machines: Vec<SoundComputer<P>>,
+    topology: Topology,
+    step_budget: Option<usize>,
+    steps: usize,
+    traffic: Option<TrafficLog>,
+}
+
+impl<P: IoPolicy> Network<P> {
+    pub fn new(machines: Vec<SoundComputer<P>>, topology: Topology) -> Self {
+        Self {
+            machines,
+            topology,
+            step_budget: None,
+            steps: 0,
+            traffic: None,
+        }
+    }
+
+    /// Sets the maximum total number of instructions executed across all machines by
+    /// Network::run, or removes the limit if None is given. Defaults to no limit.
+    pub fn set_step_budget(&mut self, step_budget: Option<usize>) {
+        self.step_budget = step_budget;
+    }
+
+    /// Returns the total number of instructions executed across all machines in the network.
+    pub fn steps(&self) -> usize {
+        return self.steps;
+    }
+
+    /// Starts recording every value delivered between machines, discarding any traffic already
+    /// recorded.
+    pub fn record_traffic(&mut self) {
+        self.traffic = Some(TrafficLog::new());
+    }
+
+    /// Returns the traffic recorded so far, if recording has been started.
+    pub fn traffic(&self) -> Option<&TrafficLog> {
+        return self.traffic.as_ref();
+    }
+
+    /// Stops recording traffic, returning the traffic recorded.
+    pub fn take_traffic(&mut self) -> Option<TrafficLog> {
+        return self.traffic.take();
+    }
+
+    /// Returns the number of machines in the network.
+    pub fn len(&self) -> usize {
+        return self.machines.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.machines.is_empty();
+    }
+
+    pub fn machine(&self, index: usize) -> &SoundComputer<P> {
+        return &self.machines[index];
+    }
+
+    pub fn machine_mut(&mut self, index: usize) -> &mut SoundComputer<P> {
+        return &mut self.machines[index];
+    }
+
+    /// Consumes the network, returning the machines it hosted.
+    pub fn into_machines(self) -> Vec<SoundComputer<P>> {
+        return self.machines;
+    }
+
+    /// Checks if every machine in the network has either halted or is blocked waiting on input.
+    fn is_stuck(&self) -> bool {
+        return self.machines.iter().all(|x| x.is_halted() || x.is_awaiting_input());
+    }
+
+    /// Runs each machine in turn until it halts, blocks on input or uses up its time slice, then
+    /// delivers any values it sent. A fault in any machine stops the round.
+    pub fn step_round(&mut self) -> Result<(), NetworkError> {
+        self.run_round(None)?;
+        return Ok(());
+    }
+
+    /// Runs a round of the network, executing no more than the given number of instructions in
+    /// total. Returns the number of instructions executed.
+    fn run_round(&mut self, budget: Option<usize>) -> Result<usize, NetworkError> {
+        let mut executed: usize = 0;
+        for source in 0..self.machines.len() {
+            let slice = match budget {
+                Some(budget) => TIME_SLICE.min(budget - executed),
+                None => TIME_SLICE,
+            };
+            // Values already queued before the machine runs are delivered with the current step
+            while let Some(output) = self.machines[source].pop_output() {
+                self.deliver(source, output, self.steps);
+            }
+            // Stop after each snd so the value is delivered with the step at which it was sent
+            let mut slice_executed: usize = 0;
+            loop {
+                let start = self.machines[source].instructions_executed();
+                let stop = match self.machines[source].run_until(RunUntil::Output, Some(slice - slice_executed)) {
+                    Ok(stop) => stop,
+                    Err(error) => return Err(NetworkError { machine: source, error }),
+                };
+                let delta = self.machines[source].instructions_executed() - start;
+                slice_executed += delta;
+                self.steps += delta;
+                let step = if stop == StopReason::Output { self.steps - 1 } else { self.steps };
+                while let Some(output) = self.machines[source].pop_output() {
+                    self.deliver(source, output, step);
+                }
+                if stop != StopReason::Output {
+                    break;
+                }
+            }
+            executed += slice_executed;
+        }
+        return Ok(executed);
+    }
+
+    /// Captures the execution state of every machine in the network, excluding the send, receive
+    /// and opcode counters.
+    fn execution_state(&self) -> Vec<SoundComputerState<P>> {
+        return self.machines.iter().map(|x| x.snapshot().without_counters()).collect();
+    }
+
+    /// Calculates a hash of the execution state of every machine in the network, combining the
+    /// state hash of each machine.
+    fn execution_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for machine in &self.machines {
+            machine.state_hash().hash(&mut hasher);
+        }
+        return hasher.finish();
+    }
+
+    /// Finds the machine still running that has gone the most rounds without sending or
+    /// receiving, returning its index and program counter.
+    fn stuck_machine(&self, idle_rounds: &[usize]) -> (usize, usize) {
+        let running = (0..self.machines.len())
+            .filter(|x| !self.machines[*x].is_halted() && !self.machines[*x].is_awaiting_input());
+        let machine = running.rev().max_by_key(|x| idle_rounds[*x]).unwrap_or(0);
+        return (machine, self.machines[machine].prog_c());
+    }
+
+    /// Delivers a value sent by the source machine at the given step to each of its destinations,
+    /// recording each delivery if traffic is being recorded.
+    fn deliver(&mut self, source: usize, value: i64, step: usize) {
+        for dest in self.topology.destinations(source, self.machines.len()) {
+            if let Some(traffic) = &mut self.traffic {
+                traffic.push(TrafficRecord { step, source, dest, value });
+            }
+            self.machines[dest].push_input(value);
+        }
+    }
+
+    /// Runs all machines in the network until every machine has halted, the network reaches a
+    /// global deadlock or livelock or the step budget is used up, or until any machine faults.
+    ///
+    /// Livelock is detected with Brent's cycle detection algorithm, comparing the state of every
+    /// machine at the end of each round against a single checkpoint that is moved forward after
+    /// each power of two rounds. States exclude the send, receive and opcode counters, and are
+    /// compared by hash first then in full, so a hash collision cannot be mistaken for a repeated
+    /// state. A repeated state is found within a few cycle lengths of the network first entering
+    /// it. A machine looping forever without ever repeating its register values can only be
+    /// stopped by the step budget.
+    pub fn run(&mut self) -> Result<NetworkReport, NetworkError> {
+        let mut checkpoint = self.execution_state();
+        let mut checkpoint_hash = self.execution_hash();
+        let mut power: usize = 1;
+        let mut rounds_since_checkpoint: usize = 0;
+        let mut idle_rounds = vec![0; self.machines.len()];
+        let mut executed: usize = 0;
+        let outcome = loop {
+            if self.is_stuck() {
+                if self.machines.iter().all(|x| x.is_halted()) {
+                    break NetworkOutcome::AllHalted;
+                }
+                break NetworkOutcome::Deadlock;
+            }
+            let remaining = self.step_budget.map(|x| x - executed);
+            if remaining == Some(0) {
+                let (machine, pc) = self.stuck_machine(&idle_rounds);
+                break NetworkOutcome::BudgetExhausted { machine, pc };
+            }
+            let io_counts = self.machines.iter().map(|x| x.snd_count() + x.rcv_count()).collect::<Vec<usize>>();
+            executed += self.run_round(remaining)?;
+            for (i, machine) in self.machines.iter().enumerate() {
+                if machine.snd_count() + machine.rcv_count() == io_counts[i] {
+                    idle_rounds[i] += 1;
+                } else {
+                    idle_rounds[i] = 0;
+                }
+            }
+            let hash = self.execution_hash();
+            if hash == checkpoint_hash && self.execution_state() == checkpoint {
+                let (machine, pc) = self.stuck_machine(&idle_rounds);
+                break NetworkOutcome::Livelock { machine, pc };
+            }
+            rounds_since_checkpoint += 1;
+            if rounds_since_checkpoint == power {
+                checkpoint = self.execution_state();
+                checkpoint_hash = hash;
+                power *= 2;
+                rounds_since_checkpoint = 0;
+            }
+        };
+        return Ok(NetworkReport {
+            outcome,
+            sends: self.machines.iter().map(|x| x.snd_count()).collect(),
+            receives: self.machines.iter().map(|x| x.rcv_count()).collect(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::parse_program;
+
+    #[test]
+    fn test_network_pairwise_duet() {
+        let input = std::fs::read_to_string("./input/2017/test/test_day18_02.txt").unwrap();
+        let program = parse_program(&input).unwrap();
+        let mut machine_1 = SoundComputer::new(&program);
+        machine_1.update_register('p', 1);
+        let mut network = Network::new(vec![SoundComputer::new(&program), machine_1], Topology::Pairwise);
+        let report = network.run().unwrap();
+        assert_eq!(NetworkOutcome::Deadlock, report.outcome);
+        assert_eq!(vec![3, 3], report.sends);
+        assert_eq!(vec![3, 3], report.receives);
+        assert_eq!(Some(1), network.machine(0).register('c'));
+    }
+
+    #[test]
+    fn test_network_livelock_and_budget() {
+        // Machine 0 spins on a jump to itself, while machine 1 waits on input forever
+        let program = parse_program("set a 1\njgz a 0").unwrap();
+        let waiting = parse_program("rcv a").unwrap();
+        let machines = vec![SoundComputer::new(&program), SoundComputer::new(&waiting)];
+        let report = Network::new(machines, Topology::Pairwise).run().unwrap();
+        assert_eq!(NetworkOutcome::Livelock { machine: 0, pc: 1 }, report.outcome);
+        // Counting loop never repeats a state, so is only stopped by the step budget
+        let program = parse_program("add a 1\njgz 1 -1").unwrap();
+        let mut network = Network::new(vec![SoundComputer::new(&waiting), SoundComputer::new(&program)], Topology::Ring);
+        network.set_step_budget(Some(25_001));
+        let report = network.run().unwrap();
+        assert_eq!(NetworkOutcome::BudgetExhausted { machine: 1, pc: 1 }, report.outcome);
+        assert_eq!(Some(12_501), network.machine(1).register('a'));
+    }
+
+    #[test]
+    fn test_network_output_queued_before_run() {
+        // Machine 0 has already sent a value before joining the network
+        let program = parse_program("snd 5\nrcv a").unwrap();
+        let mut machine_0 = SoundComputer::new(&program);
+        machine_0.step().unwrap();
+        let mut network = Network::new(vec![machine_0, SoundComputer::new(&program)], Topology::Pairwise);
+        network.record_traffic();
+        let report = network.run().unwrap();
+        assert_eq!(NetworkOutcome::AllHalted, report.outcome);
+        let records = network.traffic().unwrap().records().iter().map(|x| (x.step, x.source)).collect::<Vec<_>>();
+        assert_eq!(vec![(0, 0), (0, 1)], records);
+    }
+
+    #[test]
+    fn test_topology_destinations() {
+        assert_eq!(vec![1], Topology::Pairwise.destinations(0, 3));
+        assert!(Topology::Pairwise.destinations(2, 3).is_empty());
+        assert_eq!(vec![0], Topology::Ring.destinations(2, 3));
+        assert_eq!(vec![0, 2], Topology::Broadcast.destinations(1, 3));
+    }
+}
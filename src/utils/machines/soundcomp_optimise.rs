@@ -0,0 +1,352 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use super::soundcomp::{Operand, RunUntil, SoundComputer, SoundComputerInstruction, StopReason};
+use super::soundcomp_error::MachineError;
+
+/// Counted loop recognised by the peephole optimiser, executed by the SoundComputer as a single
+/// fused macro-instruction.
+///
+/// A fused instruction replaces only the head of its loop, with the rest of the loop body left in
+/// place so instruction indices and jump offsets are unchanged. If the register values at the head
+/// do not satisfy the guards of the kernel, the fused instruction falls back to executing the
+/// original head instruction and the loop runs as written.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum FusedKernel {
+    /// Multiply-accumulate loop, replacing the following with "target += addend * counter" and
+    /// "counter = 0". Applies when the counter is at least 1.
+    ///
+    /// ```text
+    /// add target addend
+    /// add counter -1      (or "sub counter 1")
+    /// jnz counter -2      (or "jgz counter -2")
+    /// ```
+    MulAdd { target: char, addend: Operand, counter: char },
+    /// Divisor search loop from AoC 2017 Day 23, clearing the flag if the number is the product of
+    /// the divisor and any factor from the initial factor value up to the number. Applies when the
+    /// divisor is positive and the initial factor is non-negative and below the number.
+    ///
+    /// ```text
+    /// set temp divisor
+    /// mul temp factor
+    /// sub temp number
+    /// jnz temp 2
+    /// set flag 0
+    /// sub factor -1       (or "add factor 1")
+    /// set temp factor
+    /// sub temp number
+    /// jnz temp -8
+    /// ```
+    DivisorSearch { temp: char, divisor: char, factor: char, number: char, flag: char },
+}
+
+impl FusedKernel {
+    /// Returns the number of instructions in the loop replaced by the kernel.
+    pub fn loop_len(&self) -> usize {
+        match self {
+            FusedKernel::MulAdd { .. } => return 3,
+            FusedKernel::DivisorSearch { .. } => return 9,
+        }
+    }
+
+    /// Returns the original loop head instruction, executed when the guards of the kernel fail.
+    pub fn fallback(&self) -> SoundComputerInstruction {
+        match *self {
+            FusedKernel::MulAdd { target, addend, counter: _ } => {
+                return SoundComputerInstruction::Add { value_1: Operand::Register(target), value_2: addend };
+            }
+            FusedKernel::DivisorSearch { temp, divisor, .. } => {
+                return SoundComputerInstruction::Set {
+                    value_1: Operand::Register(temp),
+                    value_2: Operand::Register(divisor),
+                };
+            }
+        }
+    }
+
+    /// Returns the names of every register used by the loop replaced by the kernel.
+    pub fn registers(&self) -> Vec<char> {
+        match *self {
+            FusedKernel::MulAdd { target, addend, counter } => {
+                let mut registers = vec![target, counter];
+                if let Operand::Register(reg) = addend {
+                    registers.push(reg);
+                }
+                return registers;
+            }
+            FusedKernel::DivisorSearch { temp, divisor, factor, number, flag } => {
+                return vec![temp, divisor, factor, number, flag];
+            }
+        }
+    }
+
+    /// Calculates the register values written by running the loop to completion from the given
+    /// register values. None is returned if the guards of the kernel are not satisfied, including
+    /// when any intermediate result of the loop would overflow.
+    pub(crate) fn evaluate(&self, read: impl Fn(&Operand) -> Option<i64>) -> Option<Vec<(char, i64)>> {
+        match *self {
+            FusedKernel::MulAdd { target, addend, counter } => {
+                let count = read(&Operand::Register(counter))?;
+                if count < 1 {
+                    return None;
+                }
+                // Partial sums move monotonically, so a final result in range never overflowed
+                let product = read(&addend)?.checked_mul(count)?;
+                let result = read(&Operand::Register(target))?.checked_add(product)?;
+                return Some(vec![(target, result), (counter, 0)]);
+            }
+            FusedKernel::DivisorSearch { temp, divisor, factor, number, flag } => {
+                let divisor_value = read(&Operand::Register(divisor))?;
+                let factor_value = read(&Operand::Register(factor))?;
+                let number_value = read(&Operand::Register(number))?;
+                if divisor_value < 1 || factor_value < 0 || factor_value >= number_value {
+                    return None;
+                }
+                // Largest product computed by the loop is below divisor * number
+                divisor_value.checked_mul(number_value)?;
+                let mut writes = vec![(temp, 0), (factor, number_value)];
+                if number_value % divisor_value == 0 {
+                    let quotient = number_value / divisor_value;
+                    if quotient >= factor_value && quotient < number_value {
+                        writes.push((flag, 0));
+                    }
+                }
+                return Some(writes);
+            }
+        }
+    }
+}
+
+impl fmt::Display for FusedKernel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FusedKernel::MulAdd { target, addend, counter } => write!(f, "muladd {} {} {}", target, addend, counter),
+            FusedKernel::DivisorSearch { temp, divisor, factor, number, flag } => {
+                write!(f, "divsearch {} {} {} {} {}", temp, divisor, factor, number, flag)
+            }
+        }
+    }
+}
+
+/// Checks if the instruction adds one to the given register ("add reg 1" or "sub reg -1").
+fn is_increment(instruction: &SoundComputerInstruction, reg: char) -> bool {
+    match instruction {
+        SoundComputerInstruction::Add { value_1: Operand::Register(x), value_2: Operand::Value(1) } |
+        SoundComputerInstruction::Sub { value_1: Operand::Register(x), value_2: Operand::Value(-1) } => {
+            return *x == reg;
+        }
+        _ => return false,
+    }
+}
+
+/// Checks if the instruction subtracts one from the given register ("add reg -1" or "sub reg 1").
+fn is_decrement(instruction: &SoundComputerInstruction, reg: char) -> bool {
+    match instruction {
+        SoundComputerInstruction::Add { value_1: Operand::Register(x), value_2: Operand::Value(-1) } |
+        SoundComputerInstruction::Sub { value_1: Operand::Register(x), value_2: Operand::Value(1) } => {
+            return *x == reg;
+        }
+        _ => return false,
+    }
+}
+
+/// Checks if the instruction jumps by the given offset while the given register is non-zero. A
+/// "jgz" is accepted in place of "jnz" where the register is known to stay positive until zero.
+fn is_loop_jump(instruction: &SoundComputerInstruction, reg: char, offset: i64, allow_jgz: bool) -> bool {
+    match instruction {
+        SoundComputerInstruction::Jnz { value_1: Operand::Register(x), value_2: Operand::Value(y) } => {
+            return *x == reg && *y == offset;
+        }
+        SoundComputerInstruction::Jgz { value_1: Operand::Register(x), value_2: Operand::Value(y) } => {
+            return allow_jgz && *x == reg && *y == offset;
+        }
+        _ => return false,
+    }
+}
+
+/// Checks if the instruction is "set dest source" with both operands naming registers, returning
+/// the names of the two registers.
+fn as_register_set(instruction: &SoundComputerInstruction) -> Option<(char, char)> {
+    match instruction {
+        SoundComputerInstruction::Set { value_1: Operand::Register(x), value_2: Operand::Register(y) } => {
+            return Some((*x, *y));
+        }
+        _ => return None,
+    }
+}
+
+/// Attempts to match a multiply-accumulate loop starting at the given instruction.
+fn match_mul_add(window: &[SoundComputerInstruction]) -> Option<FusedKernel> {
+    if window.len() < 3 {
+        return None;
+    }
+    let (target, addend) = match window[0] {
+        SoundComputerInstruction::Add { value_1: Operand::Register(x), value_2 } => (x, value_2),
+        _ => return None,
+    };
+    let counter = match window[1] {
+        SoundComputerInstruction::Add { value_1: Operand::Register(x), value_2: _ } |
+        SoundComputerInstruction::Sub { value_1: Operand::Register(x), value_2: _ } => x,
+        _ => return None,
+    };
+    if counter == target || addend == Operand::Register(target) || addend == Operand::Register(counter) {
+        return None;
+    }
+    if !is_decrement(&window[1], counter) || !is_loop_jump(&window[2], counter, -2, true) {
+        return None;
+    }
+    return Some(FusedKernel::MulAdd { target, addend, counter });
+}
+
+/// Attempts to match a divisor search loop starting at the given instruction.
+fn match_divisor_search(window: &[SoundComputerInstruction]) -> Option<FusedKernel> {
+    if window.len() < 9 {
+        return None;
+    }
+    let (temp, divisor) = as_register_set(&window[0])?;
+    let factor = match window[1] {
+        SoundComputerInstruction::Mul { value_1: Operand::Register(x), value_2: Operand::Register(y) } if x == temp => y,
+        _ => return None,
+    };
+    let number = match window[2] {
+        SoundComputerInstruction::Sub { value_1: Operand::Register(x), value_2: Operand::Register(y) } if x == temp => y,
+        _ => return None,
+    };
+    let flag = match window[4] {
+        SoundComputerInstruction::Set { value_1: Operand::Register(x), value_2: Operand::Value(0) } => x,
+        _ => return None,
+    };
+    let mut registers = vec![temp, divisor, factor, number, flag];
+    registers.sort_unstable();
+    registers.dedup();
+    if registers.len() != 5 {
+        return None;
+    }
+    let matches = is_loop_jump(&window[3], temp, 2, false) &&
+        is_increment(&window[5], factor) &&
+        as_register_set(&window[6]) == Some((temp, factor)) &&
+        window[7] == window[2] &&
+        is_loop_jump(&window[8], temp, -8, false);
+    if !matches {
+        return None;
+    }
+    return Some(FusedKernel::DivisorSearch { temp, divisor, factor, number, flag });
+}
+
+/// Runs the peephole optimiser over the given program, replacing the head of each recognised
+/// counted loop with a fused instruction. The optimised program has the same length as the original
+/// and produces identical register state, but the opcode counts and profile of the SoundComputer
+/// reflect the fused instructions executed rather than the original loops.
+pub fn optimise_program(instructions: &[SoundComputerInstruction]) -> Vec<SoundComputerInstruction> {
+    let mut optimised = instructions.to_vec();
+    for i in 0..instructions.len() {
+        let window = &instructions[i..];
+        if let Some(kernel) = match_divisor_search(window).or_else(|| match_mul_add(window)) {
+            optimised[i] = SoundComputerInstruction::Fused { kernel };
+        }
+    }
+    return optimised;
+}
+
+/// Failure found while verifying an optimised program against the original.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum VerificationError {
+    /// The original program faulted, or the optimised program faulted when the original did not.
+    Fault { optimised: bool, error: MachineError },
+    /// The programs halted with different register values.
+    RegisterMismatch { original: BTreeMap<char, i64>, optimised: BTreeMap<char, i64> },
+    /// The original or optimised program did not halt or block on input within the step budget.
+    BudgetExhausted { optimised: bool, budget: usize },
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerificationError::Fault { optimised: false, error } => write!(f, "original program faulted: {}", error),
+            VerificationError::Fault { optimised: true, error } => write!(f, "optimised program faulted: {}", error),
+            VerificationError::RegisterMismatch { original, optimised } => {
+                write!(f, "register state differs on halt: {:?} != {:?}", original, optimised)
+            }
+            VerificationError::BudgetExhausted { optimised, budget } => {
+                let program = if *optimised { "optimised" } else { "original" };
+                write!(f, "{} program did not stop within {} steps", program, budget)
+            }
+        }
+    }
+}
+
+impl Error for VerificationError {}
+
+/// Verification mode for the peephole optimiser. Runs the original program and its optimised form
+/// from the given initial register values until both halt or block on input, checking that the
+/// register state is identical. Returns the final register state if the programs agree. Each
+/// program may execute at most the given number of instructions, so verifying a program that never
+/// stops fails rather than running forever.
+pub fn verify_optimisation(
+    instructions: &[SoundComputerInstruction],
+    initial_registers: &[(char, i64)],
+    budget: usize,
+) -> Result<BTreeMap<char, i64>, VerificationError> {
+    let mut original = SoundComputer::new(instructions);
+    let mut optimised = SoundComputer::new(&optimise_program(instructions));
+    for (reg, value) in initial_registers {
+        original.update_register(*reg, *value);
+        optimised.update_register(*reg, *value);
+    }
+    for (optimised, sound_comp) in [(false, &mut original), (true, &mut optimised)] {
+        match sound_comp.run_until(RunUntil::Block, Some(budget)) {
+            Ok(StopReason::BudgetExhausted) => return Err(VerificationError::BudgetExhausted { optimised, budget }),
+            Ok(_) => (),
+            Err(error) => return Err(VerificationError::Fault { optimised, error }),
+        }
+    }
+    let original_registers = original.snapshot().registers().clone();
+    let optimised_registers = optimised.snapshot().registers().clone();
+    if original_registers != optimised_registers {
+        return Err(VerificationError::RegisterMismatch { original: original_registers, optimised: optimised_registers });
+    }
+    return Ok(original_registers);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::parse_program;
+
+    /// Counts the composite numbers between b and c (in steps of 17), as in AoC 2017 Day 23 part 2.
+    const COMPOSITE_COUNT: &str = "set f 1\nset d 2\nset e 2\nset g d\nmul g e\nsub g b\njnz g 2\nset f 0\n\
+        sub e -1\nset g e\nsub g b\njnz g -8\nsub d -1\nset g d\nsub g b\njnz g -13\njnz f 2\nsub h -1\n\
+        set g b\nsub g c\njnz g 2\njnz 1 3\nsub b -17\njnz 1 -23";
+
+    #[test]
+    fn test_optimise_divisor_search() {
+        let program = parse_program(COMPOSITE_COUNT).unwrap();
+        let optimised = optimise_program(&program);
+        assert_eq!(program.len(), optimised.len());
+        let kernel = FusedKernel::DivisorSearch { temp: 'g', divisor: 'd', factor: 'e', number: 'b', flag: 'f' };
+        assert_eq!(SoundComputerInstruction::Fused { kernel }, optimised[3]);
+        assert_eq!(program[3], kernel.fallback());
+        let registers = verify_optimisation(&program, &[('b', 107), ('c', 107 + 17 * 3)], 1_000_000).unwrap();
+        let composites = (0..=3).map(|x| 107 + 17 * x).filter(|x: &i64| (2..*x).any(|y| x % y == 0)).count();
+        assert_eq!(Some(&(composites as i64)), registers.get(&'h'));
+    }
+
+    #[test]
+    fn test_optimise_mul_add() {
+        let program = parse_program("set c 5\nset b -3\nadd a b\nsub c 1\njgz c -2\nadd a 100\njgz c -4").unwrap();
+        let optimised = optimise_program(&program);
+        let kernel = FusedKernel::MulAdd { target: 'a', addend: Operand::Register('b'), counter: 'c' };
+        assert_eq!(SoundComputerInstruction::Fused { kernel }, optimised[2]);
+        let registers = verify_optimisation(&program, &[], 1_000).unwrap();
+        assert_eq!(Some(&85), registers.get(&'a'));
+        // Counter starting at zero falls back to a single pass of the original loop
+        let registers = verify_optimisation(&program[1..], &[], 1_000).unwrap();
+        assert_eq!(Some(&97), registers.get(&'a'));
+        assert_eq!(Some(&-1), registers.get(&'c'));
+        // Loop that never stops is reported once the budget is used up
+        let program = parse_program("set a 1\njgz a 0").unwrap();
+        let err = verify_optimisation(&program, &[], 100).unwrap_err();
+        assert_eq!(VerificationError::BudgetExhausted { optimised: false, budget: 100 }, err);
+    }
+}
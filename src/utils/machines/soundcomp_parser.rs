@@ -0,0 +1,223 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use super::soundcomp::{Operand, SoundComputerInstruction};
+
+/// Categorises the reasons a line of SoundComputer source text can fail to parse.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ParseErrorKind {
+    UnknownMnemonic,
+    WrongArity { expected: usize, found: usize },
+    InvalidOperand,
+}
+
+/// Error returned when SoundComputer source text cannot be parsed. Line and column numbers are
+/// 1-indexed, with the column pointing at the start of the offending token.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    line: usize,
+    column: usize,
+    token: String,
+    kind: ParseErrorKind,
+}
+
+impl ParseError {
+    pub fn new(line: usize, column: usize, token: &str, kind: ParseErrorKind) -> Self {
+        Self {
+            line,
+            column,
+            token: token.to_string(),
+            kind,
+        }
+    }
+
+    /// Returns the line number (1-indexed) at which the error occurred.
+    pub fn line(&self) -> usize {
+        return self.line;
+    }
+
+    /// Returns the column number (1-indexed) of the start of the offending token.
+    pub fn column(&self) -> usize {
+        return self.column;
+    }
+
+    /// Returns the token that caused the error.
+    pub fn token(&self) -> &str {
+        return &self.token;
+    }
+
+    pub fn kind(&self) -> &ParseErrorKind {
+        return &self.kind;
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::UnknownMnemonic => write!(f, "unknown mnemonic '{}'", self.token),
+            ParseErrorKind::WrongArity { expected, found } => write!(
+                f,
+                "'{}' expects {} operand(s) but found {}",
+                self.token, expected, found
+            ),
+            ParseErrorKind::InvalidOperand => write!(
+                f,
+                "invalid operand '{}' (expected integer or single-letter register name)",
+                self.token
+            ),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl FromStr for Operand {
+    type Err = ParseError;
+
+    /// Parses an operand token. Integers are accepted as immediate values, and single lowercase
+    /// letters as register names. The returned error is reported at line 1, column 1.
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        if let Ok(value) = token.parse::<i64>() {
+            return Ok(Operand::Value(value));
+        }
+        let mut chars = token.chars();
+        match (chars.next(), chars.next()) {
+            (Some(reg), None) if reg.is_ascii_lowercase() => return Ok(Operand::Register(reg)),
+            _ => return Err(ParseError::new(1, 1, token, ParseErrorKind::InvalidOperand)),
+        }
+    }
+}
+
+impl SoundComputerInstruction {
+    /// Parses a single line of SoundComputer source text into an instruction.
+    pub fn parse(line: &str) -> Result<SoundComputerInstruction, ParseError> {
+        return parse_line(line, 1);
+    }
+}
+
+impl FromStr for SoundComputerInstruction {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        return SoundComputerInstruction::parse(line);
+    }
+}
+
+/// Parses a complete SoundComputer program, one instruction per line. Anything following a ';' on
+/// a line is treated as a comment. Blank lines are skipped but still counted when reporting line
+/// numbers in errors.
+pub fn parse_program(input: &str) -> Result<Vec<SoundComputerInstruction>, ParseError> {
+    let mut instructions = Vec::<SoundComputerInstruction>::new();
+    for (i, line) in input.lines().enumerate() {
+        if strip_comment(line).trim().is_empty() {
+            continue;
+        }
+        instructions.push(parse_line(line, i + 1)?);
+    }
+    return Ok(instructions);
+}
+
+/// Removes any trailing comment (started by ';') from the line.
+fn strip_comment(line: &str) -> &str {
+    match line.find(';') {
+        Some(index) => return &line[..index],
+        None => return line,
+    }
+}
+
+/// Splits the line into whitespace-separated tokens, pairing each with its 1-indexed column.
+fn tokenise(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::<(usize, &str)>::new();
+    let mut token_start: Option<usize> = None;
+    for (i, c) in line.char_indices() {
+        if c.is_whitespace() {
+            if let Some(start) = token_start {
+                tokens.push((start, &line[start..i]));
+                token_start = None;
+            }
+        } else if token_start.is_none() {
+            token_start = Some(i);
+        }
+    }
+    if let Some(start) = token_start {
+        tokens.push((start, &line[start..]));
+    }
+    // Convert byte offsets into 1-indexed character columns
+    return tokens
+        .into_iter()
+        .map(|(start, token)| (line[..start].chars().count() + 1, token))
+        .collect();
+}
+
+/// Parses a single line of source text, reporting any error against the given line number.
+fn parse_line(line: &str, line_num: usize) -> Result<SoundComputerInstruction, ParseError> {
+    let tokens = tokenise(strip_comment(line));
+    if tokens.is_empty() {
+        return Err(ParseError::new(line_num, 1, "", ParseErrorKind::UnknownMnemonic));
+    }
+    let (mnemonic_col, mnemonic) = tokens[0];
+    let operands = &tokens[1..];
+    // Determine the number of operands expected by the mnemonic
+    let expected = match mnemonic {
+        "snd" | "rcv" => 1,
+        "set" | "add" | "sub" | "mul" | "mod" | "jgz" | "jnz" => 2,
+        _ => {
+            return Err(ParseError::new(line_num, mnemonic_col, mnemonic, ParseErrorKind::UnknownMnemonic));
+        }
+    };
+    if operands.len() != expected {
+        let kind = ParseErrorKind::WrongArity { expected, found: operands.len() };
+        return Err(ParseError::new(line_num, mnemonic_col, mnemonic, kind));
+    }
+    // Parse each of the operands, re-positioning any error at the operand location
+    let mut values = Vec::<Operand>::new();
+    for (col, token) in operands {
+        match token.parse::<Operand>() {
+            Ok(operand) => values.push(operand),
+            Err(err) => return Err(ParseError::new(line_num, *col, token, err.kind)),
+        }
+    }
+    let instruction = match mnemonic {
+        "snd" => SoundComputerInstruction::Snd { value_1: values[0] },
+        "rcv" => SoundComputerInstruction::Rcv { value_1: values[0] },
+        "set" => SoundComputerInstruction::Set { value_1: values[0], value_2: values[1] },
+        "add" => SoundComputerInstruction::Add { value_1: values[0], value_2: values[1] },
+        "sub" => SoundComputerInstruction::Sub { value_1: values[0], value_2: values[1] },
+        "mul" => SoundComputerInstruction::Mul { value_1: values[0], value_2: values[1] },
+        "mod" => SoundComputerInstruction::Mod { value_1: values[0], value_2: values[1] },
+        "jgz" => SoundComputerInstruction::Jgz { value_1: values[0], value_2: values[1] },
+        "jnz" => SoundComputerInstruction::Jnz { value_1: values[0], value_2: values[1] },
+        _ => panic!("Mnemonic already validated - should not get here!"),
+    };
+    return Ok(instruction);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_program_test_input() {
+        let input = std::fs::read_to_string("./input/2017/test/test_day18_01.txt").unwrap();
+        let program = parse_program(&input).unwrap();
+        assert_eq!(10, program.len());
+        assert_eq!(
+            SoundComputerInstruction::Jgz { value_1: Operand::Register('a'), value_2: Operand::Value(-1) },
+            program[7]
+        );
+    }
+
+    #[test]
+    fn test_parse_program_errors() {
+        let err = parse_program("set a 1\n\nfoo a").unwrap_err();
+        assert_eq!((3, 1, "foo"), (err.line(), err.column(), err.token()));
+        assert_eq!(&ParseErrorKind::UnknownMnemonic, err.kind());
+        let err = parse_program("set a 1\nadd a").unwrap_err();
+        assert_eq!(&ParseErrorKind::WrongArity { expected: 2, found: 1 }, err.kind());
+        let err = parse_program("snd a\n  mul ab 2").unwrap_err();
+        assert_eq!((2, 7, "ab"), (err.line(), err.column(), err.token()));
+        assert_eq!(&ParseErrorKind::InvalidOperand, err.kind());
+    }
+}
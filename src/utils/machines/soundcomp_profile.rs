@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use enum_iterator::IntoEnumIterator;
+
+use super::soundcomp::{Opcode, SoundComputerInstruction};
+
+/// A loop detected while profiling, identified by a taken back-edge jump from the instruction at
+/// index "end" to the instruction at index "start".
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct LoopProfile {
+    start: usize,
+    end: usize,
+    iterations: usize,
+}
+
+impl LoopProfile {
+    /// Returns the index of the first instruction in the loop (the back-edge jump target).
+    pub fn start(&self) -> usize {
+        return self.start;
+    }
+
+    /// Returns the index of the jump instruction forming the back-edge of the loop.
+    pub fn end(&self) -> usize {
+        return self.end;
+    }
+
+    /// Returns the number of times the back-edge was taken.
+    pub fn iterations(&self) -> usize {
+        return self.iterations;
+    }
+
+    /// Returns the number of instructions within the loop body, including the jump.
+    pub fn body_len(&self) -> usize {
+        return self.end - self.start + 1;
+    }
+}
+
+/// Structured report of the execution profile gathered by a SoundComputer with profiling enabled.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ProfileReport {
+    instruction_counts: Vec<usize>,
+    opcode_counts: Vec<(Opcode, usize)>,
+    loops: Vec<LoopProfile>,
+}
+
+impl ProfileReport {
+    /// Returns the number of times each instruction was executed, indexed by instruction index.
+    pub fn instruction_counts(&self) -> &[usize] {
+        return &self.instruction_counts;
+    }
+
+    /// Returns the number of times each opcode was executed, in opcode declaration order.
+    pub fn opcode_counts(&self) -> &[(Opcode, usize)] {
+        return &self.opcode_counts;
+    }
+
+    /// Returns the loops detected during execution, ranked from most to least iterations.
+    pub fn loops(&self) -> &[LoopProfile] {
+        return &self.loops;
+    }
+
+    /// Returns the loop with the most iterations, if any loops were detected.
+    pub fn hottest_loop(&self) -> Option<&LoopProfile> {
+        return self.loops.first();
+    }
+}
+
+/// Records execution counts for a SoundComputer while profiling is enabled.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Profiler {
+    instruction_counts: Vec<usize>,
+    back_edges: HashMap<(usize, usize), usize>,
+}
+
+impl Profiler {
+    pub fn new(program_len: usize) -> Self {
+        Self {
+            instruction_counts: vec![0; program_len],
+            back_edges: HashMap::new(),
+        }
+    }
+
+    /// Records the execution of the instruction at the given index.
+    pub fn record_instruction(&mut self, index: usize) {
+        self.instruction_counts[index] += 1;
+    }
+
+    /// Records a jump taken from the instruction at index "from" back to the index "to".
+    pub fn record_back_edge(&mut self, from: usize, to: usize) {
+        *self.back_edges.entry((to, from)).or_insert(0) += 1;
+    }
+
+    /// Generates the report for the profile data recorded against the given instructions.
+    pub fn report(&self, instructions: &[SoundComputerInstruction]) -> ProfileReport {
+        // Total up the execution counts for each opcode
+        let mut opcode_totals = HashMap::<Opcode, usize>::new();
+        for (index, count) in self.instruction_counts.iter().enumerate() {
+            *opcode_totals.entry(instructions[index].opcode()).or_insert(0) += count;
+        }
+        let opcode_counts = Opcode::into_enum_iter()
+            .map(|x| (x, *opcode_totals.get(&x).unwrap_or(&0)))
+            .collect::<Vec<(Opcode, usize)>>();
+        // Rank loops by iteration count, breaking ties by position in the program
+        let mut loops = self
+            .back_edges
+            .iter()
+            .map(|(&(start, end), &iterations)| LoopProfile { start, end, iterations })
+            .collect::<Vec<LoopProfile>>();
+        loops.sort_by(|a, b| {
+            b.iterations.cmp(&a.iterations).then(a.start.cmp(&b.start)).then(a.end.cmp(&b.end))
+        });
+        return ProfileReport {
+            instruction_counts: self.instruction_counts.clone(),
+            opcode_counts,
+            loops,
+        };
+    }
+}
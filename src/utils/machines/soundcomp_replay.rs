@@ -0,0 +1,238 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::soundcomp::{RunUntil, SoundComputer, StopReason};
+use super::soundcomp_error::MachineError;
+use super::soundcomp_io::IoPolicy;
+
+/// Single value delivered between two machines in a Network.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct TrafficRecord {
+    /// Global step index of the snd instruction that sent the value, counting from zero the
+    /// instructions executed across every machine in the network.
+    pub step: usize,
+    pub source: usize,
+    pub dest: usize,
+    pub value: i64,
+}
+
+/// Error returned when the text form of a TrafficLog cannot be parsed. Line numbers are 1-indexed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TrafficParseError {
+    line: usize,
+    text: String,
+}
+
+impl TrafficParseError {
+    /// Returns the line number (1-indexed) at which the error occurred.
+    pub fn line(&self) -> usize {
+        return self.line;
+    }
+
+    /// Returns the text of the line that could not be parsed.
+    pub fn text(&self) -> &str {
+        return &self.text;
+    }
+}
+
+impl fmt::Display for TrafficParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: expected '<step> <source> <dest> <value>', found '{}'", self.line, self.text)
+    }
+}
+
+impl Error for TrafficParseError {}
+
+/// Summary of replaying a TrafficLog against a single machine.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct ReplayReport {
+    pub stop: StopReason,
+    /// Number of recorded values the machine received. Any values already in its input queue
+    /// before the replay are received first.
+    pub received: usize,
+    /// Values sent by the machine during the replay.
+    pub sent: Vec<i64>,
+    /// Index of the first value sent during the replay that does not match the recorded traffic,
+    /// including a value missing from either side. None if the replay matched the recording.
+    pub divergence: Option<usize>,
+}
+
+/// Record of the values delivered between the machines of a Network, in delivery order.
+///
+/// The text form has one record per line, giving the step, source machine, destination machine and
+/// value separated by whitespace. Blank lines and lines starting with '#' are ignored.
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub struct TrafficLog {
+    records: Vec<TrafficRecord>,
+}
+
+impl TrafficLog {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Returns the recorded deliveries, oldest first.
+    pub fn records(&self) -> &[TrafficRecord] {
+        return &self.records;
+    }
+
+    pub fn push(&mut self, record: TrafficRecord) {
+        self.records.push(record);
+    }
+
+    /// Returns the values delivered to the given machine, in delivery order.
+    pub fn received_by(&self, machine: usize) -> Vec<i64> {
+        return self.records.iter().filter(|x| x.dest == machine).map(|x| x.value).collect();
+    }
+
+    /// Returns the values sent by the given machine, in order. A value delivered to several
+    /// destinations is only included once, taken from the deliveries to the lowest destination.
+    pub fn sent_by(&self, machine: usize) -> Vec<i64> {
+        let dest = match self.records.iter().filter(|x| x.source == machine).map(|x| x.dest).min() {
+            Some(dest) => dest,
+            None => return vec![],
+        };
+        return self.records.iter().filter(|x| x.source == machine && x.dest == dest).map(|x| x.value).collect();
+    }
+
+    /// Writes the text form of the log to the file at the given path.
+    pub fn save<Q: AsRef<Path>>(&self, path: Q) -> io::Result<()> {
+        return fs::write(path, self.to_string());
+    }
+
+    /// Reads a log from the text file at the given path. Parse errors are returned as io::Error
+    /// values of kind InvalidData.
+    pub fn load<Q: AsRef<Path>>(path: Q) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        return text.parse::<Self>().map_err(|x| io::Error::new(io::ErrorKind::InvalidData, x));
+    }
+
+    /// Replays the recorded traffic against a single machine, standing in for its peers. Every
+    /// value delivered to the machine at the given index is pushed to its input queue, then the
+    /// machine is run until it halts, blocks on input or uses up the given budget. The values it
+    /// sends are compared against those it sent in the recording.
+    pub fn replay<P: IoPolicy>(
+        &self,
+        machine: &mut SoundComputer<P>,
+        index: usize,
+        budget: Option<usize>,
+    ) -> Result<ReplayReport, MachineError> {
+        let inputs = self.received_by(index);
+        let queued = machine.input_queue().len();
+        for input in &inputs {
+            machine.push_input(*input);
+        }
+        let stop = machine.run_until(RunUntil::Block, budget)?;
+        let mut sent = Vec::<i64>::new();
+        while let Some(output) = machine.pop_output() {
+            sent.push(output);
+        }
+        let expected = self.sent_by(index);
+        let divergence = (0..sent.len().max(expected.len())).find(|x| sent.get(*x) != expected.get(*x));
+        let consumed = queued + inputs.len() - machine.input_queue().len();
+        return Ok(ReplayReport {
+            stop,
+            received: consumed.saturating_sub(queued),
+            sent,
+            divergence,
+        });
+    }
+}
+
+impl fmt::Display for TrafficLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# step source dest value")?;
+        for record in &self.records {
+            writeln!(f, "{} {} {} {}", record.step, record.source, record.dest, record.value)?;
+        }
+        return Ok(());
+    }
+}
+
+impl FromStr for TrafficLog {
+    type Err = TrafficParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut log = TrafficLog::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || TrafficParseError { line: i + 1, text: line.to_string() };
+            let fields = line.split_whitespace().collect::<Vec<&str>>();
+            if fields.len() != 4 {
+                return Err(error());
+            }
+            log.push(TrafficRecord {
+                step: fields[0].parse().map_err(|_| error())?,
+                source: fields[1].parse().map_err(|_| error())?,
+                dest: fields[2].parse().map_err(|_| error())?,
+                value: fields[3].parse().map_err(|_| error())?,
+            });
+        }
+        return Ok(log);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{parse_program, Network, Topology};
+
+    #[test]
+    fn test_record_and_replay_duet() {
+        let input = std::fs::read_to_string("./input/2017/test/test_day18_02.txt").unwrap();
+        let program = parse_program(&input).unwrap();
+        let mut machine_1 = SoundComputer::new(&program);
+        machine_1.update_register('p', 1);
+        let mut network = Network::new(vec![SoundComputer::new(&program), machine_1.clone()], Topology::Pairwise);
+        network.record_traffic();
+        network.run().unwrap();
+        let log = network.take_traffic().unwrap();
+        assert_eq!(vec![1, 2, 1], log.sent_by(1));
+        assert_eq!(vec![1, 2, 0], log.received_by(1));
+        // Machine 0 sends at steps 0 to 2 then blocks, so machine 1 sends at steps 3 to 5
+        let steps = log.records().iter().map(|x| x.step).collect::<Vec<usize>>();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], steps);
+        // Round trip through the text form, then replay machine 1 without its peer
+        let log = log.to_string().parse::<TrafficLog>().unwrap();
+        let mut replayed = machine_1.clone();
+        let report = log.replay(&mut replayed, 1, None).unwrap();
+        assert_eq!(StopReason::AwaitingInput, report.stop);
+        assert_eq!(3, report.received);
+        assert_eq!(None, report.divergence);
+        assert_eq!(Some(2), replayed.register('b'));
+        assert_eq!(Some(0), replayed.register('c'));
+        // Values already queued are received before the recorded values
+        let mut replayed = machine_1.clone();
+        replayed.push_input(9);
+        replayed.push_input(8);
+        let report = log.replay(&mut replayed, 1, None).unwrap();
+        assert_eq!(2, report.received);
+        assert_eq!((Some(9), Some(8), Some(1)), (replayed.register('a'), replayed.register('b'), replayed.register('c')));
+        // Tampered recording diverges at the value machine 1 sends differently
+        let mut tampered = TrafficLog::new();
+        for mut record in log.records().iter().copied() {
+            if record.source == 1 && record.value == 1 {
+                record.value = 5;
+            }
+            tampered.push(record);
+        }
+        assert_eq!(Some(0), tampered.replay(&mut machine_1, 1, None).unwrap().divergence);
+    }
+
+    #[test]
+    fn test_parse_traffic_log_errors() {
+        let log = "# comment\n\n10 0 1 -7\n".parse::<TrafficLog>().unwrap();
+        assert_eq!(&[TrafficRecord { step: 10, source: 0, dest: 1, value: -7 }], log.records());
+        let error = "10 0 1 -7\n11 1 x 3".parse::<TrafficLog>().unwrap_err();
+        assert_eq!(2, error.line());
+        assert_eq!("11 1 x 3", error.text());
+        assert!("10 0 1".parse::<TrafficLog>().is_err());
+    }
+}
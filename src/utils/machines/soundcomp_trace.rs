@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use super::soundcomp::SoundComputerInstruction;
+
+/// Change made to a single register by the execution of an instruction.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct RegisterDelta {
+    pub register: char,
+    pub old_value: i64,
+    pub new_value: i64,
+}
+
+/// Observer attached to a SoundComputer, receiving a callback before and after each instruction is
+/// executed. An rcv instruction that blocks waiting on input still generates both callbacks, with
+/// the program counter left unchanged. An instruction that faults generates no after_step callback.
+pub trait ExecutionObserver {
+    /// Called before the instruction at the given program counter is executed, with the evaluated
+    /// values of each of its operands.
+    fn before_step(&mut self, pc: usize, instruction: &SoundComputerInstruction, operand_values: &[i64]);
+
+    /// Called after the instruction at the given program counter has been executed, with the new
+    /// program counter and the changes made to any register values.
+    fn after_step(&mut self, pc: usize, instruction: &SoundComputerInstruction, next_pc: usize, deltas: &[RegisterDelta]);
+}
+
+/// Single executed step captured by a TraceRecorder.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct TraceEntry {
+    pub pc: usize,
+    pub instruction: SoundComputerInstruction,
+    pub operand_values: Vec<i64>,
+    pub next_pc: usize,
+    pub deltas: Vec<RegisterDelta>,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let values = self.operand_values.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+        write!(f, "{:>4}: {:<12} [{}] -> {}", self.pc, self.instruction.to_string(), values.join(", "), self.next_pc)?;
+        for delta in &self.deltas {
+            write!(f, " {}: {} => {}", delta.register, delta.old_value, delta.new_value)?;
+        }
+        return Ok(());
+    }
+}
+
+/// ExecutionObserver that records every executed step, optionally keeping only the most recent
+/// entries.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct TraceRecorder {
+    entries: VecDeque<TraceEntry>,
+    pending_operands: Vec<i64>,
+    limit: Option<usize>,
+}
+
+impl TraceRecorder {
+    /// Creates a new TraceRecorder that keeps every entry recorded.
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Creates a new TraceRecorder that keeps only the given number of most recent entries.
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            pending_operands: vec![],
+            limit: Some(limit),
+        }
+    }
+
+    /// Returns the recorded entries, oldest first. The entries are rearranged in place if the oldest
+    /// have been dropped to stay within the limit, so a mutable borrow is needed.
+    pub fn entries(&mut self) -> &[TraceEntry] {
+        return self.entries.make_contiguous();
+    }
+
+    /// Removes all recorded entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl ExecutionObserver for TraceRecorder {
+    fn before_step(&mut self, _pc: usize, _instruction: &SoundComputerInstruction, operand_values: &[i64]) {
+        self.pending_operands = operand_values.to_vec();
+    }
+
+    fn after_step(&mut self, pc: usize, instruction: &SoundComputerInstruction, next_pc: usize, deltas: &[RegisterDelta]) {
+        if let Some(limit) = self.limit {
+            if limit == 0 {
+                return;
+            }
+            if self.entries.len() == limit {
+                self.entries.pop_front();
+            }
+        }
+        self.entries.push_back(TraceEntry {
+            pc,
+            instruction: *instruction,
+            operand_values: std::mem::take(&mut self.pending_operands),
+            next_pc,
+            deltas: deltas.to_vec(),
+        });
+    }
+}